@@ -1,39 +1,753 @@
+use crate::error::{LitError, Result};
+use crate::migrations::{self, CONFIG_VERSION};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
 
-#[derive(Serialize, Deserialize)]
+const CONFIG_FILE: &str = "config.json";
+/// Number of `config.json.bak.N` files kept next to the config.
+pub const BACKUP_COUNT: usize = 5;
+
+static CONFIG_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Config {
+    pub version: u64,
     pub profiles: HashMap<String, GitConfig>,
     pub current_profile: String,
+    /// Scope used by `switch-profile` when no scope flag is given.
+    pub default_scope: DefaultScope,
+    /// Matchers picking a profile for a repository, see `resolve`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<MatchRule>,
+}
+
+/// A rule choosing `profile` for repositories matching every matcher it
+/// sets. Higher priorities are tried first; equal ones in list order.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct MatchRule {
+    pub profile: String,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub priority: i32,
+    /// Glob matched against the repository's path, e.g. `~/work/**`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Regular expression matched against each remote URL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// Glob matched against the checked-out branch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Glob matched against this machine's hostname.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// `local` writes to the repository config when run inside one and falls
+/// back to `global` elsewhere.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultScope {
+    #[default]
+    Global,
+    Local,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            profiles: HashMap::new(),
+            current_profile: String::new(),
+            default_scope: DefaultScope::default(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
 pub struct GitConfig {
     pub user_name: String,
     pub user_email: String,
+    /// Additional git config keys applied with the profile. A key may carry
+    /// several values (e.g. `remote.origin.push`).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra_config: BTreeMap<String, Vec<String>>,
+    /// `user.signingkey`: a GPG key id, or an SSH key when `gpg_format` is `ssh`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
+    /// `gpg.format`: `openpgp`, `x509` or `ssh`. Unset means git's default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpg_format: Option<String>,
+    /// `commit.gpgsign`
+    #[serde(skip_serializing_if = "is_false")]
+    pub sign_commits: bool,
+    /// `tag.gpgsign`
+    #[serde(skip_serializing_if = "is_false")]
+    pub sign_tags: bool,
+    /// Private key git should push and fetch with, applied as `core.sshCommand`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_key: Option<String>,
+    /// Load `ssh_key` into ssh-agent on switch, unloading the previous
+    /// profile's key.
+    #[serde(skip_serializing_if = "is_false")]
+    pub agent: bool,
+    /// Host aliases written to the managed block of `~/.ssh/config`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ssh_hosts: Vec<SshHost>,
+    /// `init.defaultBranch`, also used as the initial branch by `lit init`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+    /// `commit.template`: file whose contents prefill commit messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_template: Option<String>,
+    /// Directory trees whose repositories use this profile, through a
+    /// generated `includeIf "gitdir:..."` in the global git config.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub directories: Vec<String>,
+    /// Remote URL patterns (e.g. `*github.com:acme/*`) whose repositories
+    /// use this profile, through `includeIf "hasconfig:remote.*.url:..."`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remote_patterns: Vec<String>,
+    /// `url.<base>.insteadOf` rules installed while the profile is active.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub url_rewrites: Vec<UrlRewrite>,
+}
+
+/// A `Host <alias>` entry pointing at a real host with the profile's key,
+/// e.g. `github.com-work` for `github.com`.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct SshHost {
+    pub alias: String,
+    pub hostname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+/// Rewrite remote URLs starting with `from` to start with `to` instead,
+/// e.g. `git@github.com:acme/` to `git@github.com-work:acme/`.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct UrlRewrite {
+    pub from: String,
+    pub to: String,
+    /// Only rewrite push URLs (`pushInsteadOf`).
+    #[serde(skip_serializing_if = "is_false")]
+    pub push_only: bool,
+}
+
+impl UrlRewrite {
+    /// The git config key carrying this rule; `from` is its value.
+    pub fn key(&self) -> String {
+        let variable = if self.push_only {
+            "pushinsteadof"
+        } else {
+            "insteadof"
+        };
+        format!("url.{}.{}", self.to, variable)
+    }
+}
+
+/// Values accepted for `gpg_format`.
+pub const GPG_FORMATS: &[&str] = &["openpgp", "x509", "ssh"];
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn is_zero(n: &i32) -> bool {
+    *n == 0
+}
+
+/// Use `path` as the config file for the rest of the process (the `--config` flag).
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_OVERRIDE.set(path);
+}
+
+/// Resolve the config file: `--config`, then `$LIT_CONFIG`, then the XDG config dir.
+pub fn config_path() -> PathBuf {
+    if let Some(path) = CONFIG_OVERRIDE.get() {
+        return path.clone();
+    }
+    if let Some(path) = env::var_os("LIT_CONFIG").filter(|p| !p.is_empty()) {
+        return PathBuf::from(path);
+    }
+    config_dir().join(CONFIG_FILE)
+}
+
+/// Expand a leading `~/` to `$HOME`, as profiles store key paths the way
+/// users type them.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn config_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|p| !p.is_empty()) {
+        return PathBuf::from(dir).join("lit");
+    }
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    home.join(".config").join("lit")
+}
+
+/// Whether `path` holds a lit config: JSON with a `profiles` object that
+/// loads as one.
+fn is_lit_config(path: &Path) -> bool {
+    let Ok(config_str) = fs::read_to_string(path) else {
+        return false;
+    };
+    let has_profiles = serde_json::from_str::<serde_json::Value>(&config_str)
+        .is_ok_and(|value| value.get("profiles").is_some_and(|p| p.is_object()));
+    has_profiles && load_str(path, &config_str).is_ok()
+}
+
+/// Offer to move a `config.json` left in the working directory by older
+/// versions of lit into the resolved config location. Only asks on a
+/// terminal, and only for files that are lit configs.
+pub fn migrate_legacy_config() -> io::Result<()> {
+    let target = config_path();
+    let legacy = Path::new(CONFIG_FILE);
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    if target.exists() || !legacy.is_file() || !interactive || !is_lit_config(legacy) {
+        return Ok(());
+    }
+    if let (Ok(a), Ok(b)) = (legacy.canonicalize(), target.canonicalize()) {
+        if a == b {
+            return Ok(());
+        }
+    }
+
+    print!(
+        "Found {} in the current directory. Move it to {}? [y/N] ",
+        CONFIG_FILE,
+        target.display()
+    );
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        return Ok(());
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(legacy, &target).is_err() {
+        fs::copy(legacy, &target)?;
+        fs::remove_file(legacy)?;
+    }
+    println!("Moved config to {}.", target.display());
+    Ok(())
+}
+
+pub fn load_config() -> Result<Config> {
+    let path = config_path();
+    let config_str = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let (migrated, applied) = migrate_str(&path, &config_str)?;
+    let config = parse_config(&path, &migrated)?;
+    if !applied.is_empty() {
+        write_config(&path, &migrated)?;
+    }
+    Ok(config)
+}
+
+fn invalid_config(path: &Path, e: impl std::fmt::Display) -> LitError {
+    LitError::InvalidConfig(format!("{}: {}", path.display(), e))
+}
+
+/// Run the migration chain over `config_str`, returning the upgraded document
+/// and the steps applied.
+fn migrate_str(path: &Path, config_str: &str) -> Result<(String, Vec<String>)> {
+    let mut value: serde_json::Value =
+        serde_json::from_str(config_str).map_err(|e| invalid_config(path, e))?;
+    let applied = migrations::migrate(&mut value).map_err(|e| invalid_config(path, e))?;
+    let migrated = serde_json::to_string_pretty(&value).unwrap();
+    Ok((migrated, applied))
+}
+
+/// Deserialize a config document already at the current schema version.
+fn parse_config(path: &Path, config_str: &str) -> Result<Config> {
+    serde_json::from_str(config_str).map_err(|e| invalid_config(path, e))
+}
+
+/// Migrate and deserialize a config document of any supported version.
+fn load_str(path: &Path, config_str: &str) -> Result<Config> {
+    let (migrated, _) = migrate_str(path, config_str)?;
+    parse_config(path, &migrated)
+}
+
+/// Upgrade the config file to the current schema version, backing up the old
+/// one first. With `dry_run` nothing is written. Returns the steps and the
+/// upgraded document.
+pub fn migrate_config(dry_run: bool) -> Result<(Vec<String>, String)> {
+    let path = config_path();
+    let config_str = fs::read_to_string(&path)?;
+    let (migrated, applied) = migrate_str(&path, &config_str)?;
+    parse_config(&path, &migrated)?;
+    if !dry_run && !applied.is_empty() {
+        write_config(&path, &migrated)?;
+    }
+    Ok((applied, migrated))
+}
+
+pub fn save_config(config: &Config) -> Result<()> {
+    let path = config_path();
+    if let Ok(on_disk) = fs::read_to_string(&path) {
+        if let Err(LitError::InvalidConfig(msg)) = load_str(&path, &on_disk) {
+            return Err(LitError::InvalidConfig(format!(
+                "refusing to overwrite {}",
+                msg
+            )));
+        }
+    }
+    let mut value = serde_json::to_value(config).map_err(|e| invalid_config(&path, e))?;
+    value["version"] = serde_json::Value::from(CONFIG_VERSION);
+    let config_str = serde_json::to_string_pretty(&value).map_err(|e| invalid_config(&path, e))?;
+    write_config(&path, &config_str)?;
+    Ok(())
+}
+
+/// Validate the config file and return every problem found, or an empty list.
+pub fn check_config() -> Result<Vec<String>> {
+    let path = config_path();
+    let config_str = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let value: serde_json::Value = match serde_json::from_str(&config_str) {
+        Ok(v) => v,
+        Err(e) => return Ok(vec![e.to_string()]),
+    };
+
+    let mut problems = Vec::new();
+    let Some(root) = value.as_object() else {
+        problems.push(String::from("top level must be an object"));
+        return Ok(problems);
+    };
+    for key in root.keys() {
+        if !matches!(
+            key.as_str(),
+            "version" | "profiles" | "current_profile" | "default_scope" | "rules"
+        ) {
+            problems.push(format!("unknown key '{}'", key));
+        }
+    }
+
+    match migrations::version_of(&value) {
+        Ok(v) if v > CONFIG_VERSION => problems.push(format!(
+            "config version {} is newer than this lit supports ({})",
+            v, CONFIG_VERSION
+        )),
+        Ok(_) => {}
+        Err(e) => problems.push(e),
+    }
+
+    match root.get("profiles") {
+        None => {}
+        Some(serde_json::Value::Object(profiles)) => {
+            for (name, profile) in profiles {
+                check_profile(name, profile, &mut problems);
+            }
+        }
+        Some(_) => problems.push(String::from("'profiles' must be an object")),
+    }
+
+    match root.get("current_profile") {
+        None => {}
+        Some(serde_json::Value::String(current)) => {
+            let known = root
+                .get("profiles")
+                .and_then(|p| p.as_object())
+                .is_none_or(|p| p.contains_key(current));
+            if !current.is_empty() && !known {
+                problems.push(format!(
+                    "'current_profile' refers to unknown profile '{}'",
+                    current
+                ));
+            }
+        }
+        Some(_) => problems.push(String::from("'current_profile' must be a string")),
+    }
+
+    match root.get("rules") {
+        None => {}
+        Some(serde_json::Value::Array(rules)) => {
+            let profiles = root.get("profiles").and_then(|p| p.as_object());
+            for (i, rule) in rules.iter().enumerate() {
+                check_rule(i, rule, profiles, &mut problems);
+            }
+        }
+        Some(_) => problems.push(String::from("'rules' must be a list")),
+    }
+
+    match root.get("default_scope") {
+        None => {}
+        Some(serde_json::Value::String(scope)) if matches!(scope.as_str(), "global" | "local") => {}
+        Some(other) => problems.push(format!(
+            "'default_scope' must be \"global\" or \"local\", got {}",
+            other
+        )),
+    }
+
+    Ok(problems)
+}
+
+/// Keys a profile object may contain.
+const PROFILE_KEYS: &[&str] = &[
+    "user_name",
+    "user_email",
+    "extra_config",
+    "signing_key",
+    "gpg_format",
+    "sign_commits",
+    "sign_tags",
+    "ssh_key",
+    "agent",
+    "ssh_hosts",
+    "default_branch",
+    "commit_template",
+    "url_rewrites",
+    "directories",
+    "remote_patterns",
+];
+
+/// Keys a rule object may contain, matchers last.
+const RULE_KEYS: &[&str] = &[
+    "profile", "priority", "path", "remote", "branch", "hostname",
+];
+
+fn check_rule(
+    i: usize,
+    rule: &serde_json::Value,
+    profiles: Option<&serde_json::Map<String, serde_json::Value>>,
+    problems: &mut Vec<String>,
+) {
+    let Some(fields) = rule.as_object() else {
+        problems.push(format!("rules[{}]: must be an object", i));
+        return;
+    };
+    for key in fields.keys() {
+        if !RULE_KEYS.contains(&key.as_str()) {
+            problems.push(format!("rules[{}]: unknown key '{}'", i, key));
+        }
+    }
+    match fields.get("profile").and_then(|p| p.as_str()) {
+        None => problems.push(format!("rules[{}]: missing 'profile'", i)),
+        Some(name) if profiles.is_some_and(|p| !p.contains_key(name)) => {
+            problems.push(format!("rules[{}].profile: unknown profile '{}'", i, name))
+        }
+        Some(_) => {}
+    }
+    if fields
+        .get("priority")
+        .is_some_and(|p| p.as_i64().is_none_or(|p| i32::try_from(p).is_err()))
+    {
+        problems.push(format!("rules[{}].priority: must be an integer", i));
+    }
+    let matchers = &RULE_KEYS[2..];
+    if !matchers.iter().any(|key| fields.contains_key(*key)) {
+        problems.push(format!(
+            "rules[{}]: needs at least one of {}",
+            i,
+            matchers.join(", ")
+        ));
+    }
+    for key in matchers {
+        match fields.get(*key) {
+            None => {}
+            Some(serde_json::Value::String(pattern)) if *key == "remote" => {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    problems.push(format!("rules[{}].remote: {}", i, e));
+                }
+            }
+            Some(serde_json::Value::String(_)) => {}
+            Some(_) => problems.push(format!("rules[{}].{}: must be a string", i, key)),
+        }
+    }
 }
 
-pub fn load_config() -> Config {
-    let config_str = fs::read_to_string("config.json").unwrap_or_else(|_| String::from("{}"));
-    serde_json::from_str(&config_str).unwrap_or_else(|_| Config {
-        profiles: HashMap::new(),
-        current_profile: String::new(),
-    })
+fn check_profile(name: &str, profile: &serde_json::Value, problems: &mut Vec<String>) {
+    let Some(fields) = profile.as_object() else {
+        problems.push(format!("profiles.{}: must be an object", name));
+        return;
+    };
+    for key in fields.keys() {
+        if !PROFILE_KEYS.contains(&key.as_str()) {
+            problems.push(format!("profiles.{}: unknown key '{}'", name, key));
+        }
+    }
+    for key in ["user_name", "user_email"] {
+        match fields.get(key) {
+            None => problems.push(format!("profiles.{}: missing '{}'", name, key)),
+            Some(serde_json::Value::String(v)) if v.trim().is_empty() => {
+                problems.push(format!("profiles.{}.{}: must not be empty", name, key))
+            }
+            Some(serde_json::Value::String(_)) => {}
+            Some(_) => problems.push(format!("profiles.{}.{}: must be a string", name, key)),
+        }
+    }
+    if let Some(email) = fields.get("user_email").and_then(|v| v.as_str()) {
+        if !email.is_empty() && !email.contains('@') {
+            problems.push(format!(
+                "profiles.{}.user_email: '{}' is not an email address",
+                name, email
+            ));
+        }
+    }
+    for key in [
+        "signing_key",
+        "ssh_key",
+        "default_branch",
+        "commit_template",
+    ] {
+        if fields.get(key).is_some_and(|v| !v.is_string()) {
+            problems.push(format!("profiles.{}.{}: must be a string", name, key));
+        }
+    }
+    match fields.get("gpg_format") {
+        None => {}
+        Some(serde_json::Value::String(f)) if GPG_FORMATS.contains(&f.as_str()) => {}
+        Some(other) => problems.push(format!(
+            "profiles.{}.gpg_format: must be one of {}, got {}",
+            name,
+            GPG_FORMATS.join(", "),
+            other
+        )),
+    }
+    for key in ["sign_commits", "sign_tags", "agent"] {
+        if fields.get(key).is_some_and(|v| !v.is_boolean()) {
+            problems.push(format!("profiles.{}.{}: must be true or false", name, key));
+        }
+    }
+    if fields.get("agent").is_some_and(|v| v == true) && !fields.contains_key("ssh_key") {
+        problems.push(format!("profiles.{}.agent: requires ssh_key", name));
+    }
+    match fields.get("ssh_hosts") {
+        None => {}
+        Some(serde_json::Value::Array(hosts)) => {
+            for (i, host) in hosts.iter().enumerate() {
+                for key in ["alias", "hostname"] {
+                    let ok = host
+                        .get(key)
+                        .and_then(|v| v.as_str())
+                        .is_some_and(|v| !v.trim().is_empty() && !v.contains(char::is_whitespace));
+                    if !ok {
+                        problems.push(format!(
+                            "profiles.{}.ssh_hosts[{}].{}: must be a non-empty word",
+                            name, i, key
+                        ));
+                    }
+                }
+            }
+        }
+        Some(_) => problems.push(format!("profiles.{}.ssh_hosts: must be a list", name)),
+    }
+    for key in ["directories", "remote_patterns"] {
+        match fields.get(key) {
+            None => {}
+            Some(serde_json::Value::Array(items))
+                if items
+                    .iter()
+                    .all(|d| d.as_str().is_some_and(|d| !d.trim().is_empty())) => {}
+            Some(_) => problems.push(format!(
+                "profiles.{}.{}: must be a list of non-empty strings",
+                name, key
+            )),
+        }
+    }
+    match fields.get("url_rewrites") {
+        None => {}
+        Some(serde_json::Value::Array(rules)) => {
+            for (i, rule) in rules.iter().enumerate() {
+                for key in ["from", "to"] {
+                    let ok = rule
+                        .get(key)
+                        .and_then(|v| v.as_str())
+                        .is_some_and(|v| !v.trim().is_empty());
+                    if !ok {
+                        problems.push(format!(
+                            "profiles.{}.url_rewrites[{}].{}: must be a non-empty string",
+                            name, i, key
+                        ));
+                    }
+                }
+                if rule.get("push_only").is_some_and(|v| !v.is_boolean()) {
+                    problems.push(format!(
+                        "profiles.{}.url_rewrites[{}].push_only: must be true or false",
+                        name, i
+                    ));
+                }
+            }
+        }
+        Some(_) => problems.push(format!("profiles.{}.url_rewrites: must be a list", name)),
+    }
+    match fields.get("extra_config") {
+        None => {}
+        Some(serde_json::Value::Object(extra)) => {
+            for (key, values) in extra {
+                if !key.contains('.') {
+                    problems.push(format!(
+                        "profiles.{}.extra_config: '{}' is not a section.key name",
+                        name, key
+                    ));
+                }
+                let all_strings = values
+                    .as_array()
+                    .is_some_and(|v| v.iter().all(|v| v.is_string()));
+                if !all_strings {
+                    problems.push(format!(
+                        "profiles.{}.extra_config.{}: must be a list of strings",
+                        name, key
+                    ));
+                }
+            }
+        }
+        Some(_) => problems.push(format!("profiles.{}.extra_config: must be an object", name)),
+    }
 }
 
-pub fn save_config(config: &Config) {
-    let config_str = serde_json::to_string_pretty(config).unwrap();
-    fs::write("config.json", config_str).unwrap();
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".bak.{}", n));
+    path.with_file_name(name)
+}
+
+/// Write `contents` to a temp file, fsync it, rotate the backups and rename
+/// it over `path`, so a crash never leaves a truncated config behind.
+fn write_config(path: &Path, contents: &str) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    fs::create_dir_all(&dir)?;
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if path.exists() {
+        rotate_backups(path)?;
+    }
+    fs::rename(&tmp, path)?;
+    if let Ok(dir) = fs::File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    let oldest = backup_path(path, BACKUP_COUNT);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for n in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, n + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// Existing backups as `(N, path, modified)`, newest first.
+pub fn list_backups() -> Vec<(usize, PathBuf, Option<SystemTime>)> {
+    let path = config_path();
+    (1..=BACKUP_COUNT)
+        .map(|n| (n, backup_path(&path, n)))
+        .filter(|(_, p)| p.is_file())
+        .map(|(n, p)| {
+            let modified = fs::metadata(&p).and_then(|m| m.modified()).ok();
+            (n, p, modified)
+        })
+        .collect()
+}
+
+/// Replace the config with backup `n`. The current config becomes backup 1.
+pub fn restore_backup(n: usize) -> Result<()> {
+    let path = config_path();
+    let backup = backup_path(&path, n);
+    let contents = fs::read_to_string(&backup)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", backup.display(), e)))?;
+    load_str(&backup, &contents)?;
+    write_config(&path, &contents)?;
+    Ok(())
 }
 
-pub fn list_profiles() -> Result<(), Box<dyn std::error::Error>> {
-    let config = load_config();
-    println!("Profiles:");
+pub fn list_profiles(out: &mut impl Write) -> Result<()> {
+    let config = load_config()?;
+    writeln!(out, "Profiles:")?;
     for (name, profile) in config.profiles {
-        println!("Profile: {}", name);
-        println!("  User Name: {}", profile.user_name);
-        println!("  User Email: {}", profile.user_email);
+        writeln!(out, "Profile: {}", name)?;
+        writeln!(out, "  User Name: {}", profile.user_name)?;
+        writeln!(out, "  User Email: {}", profile.user_email)?;
+        if let Some(ref key) = profile.signing_key {
+            writeln!(
+                out,
+                "  Signing Key: {} ({})",
+                key,
+                profile.gpg_format.as_deref().unwrap_or("openpgp")
+            )?;
+        }
+        if profile.sign_commits || profile.sign_tags {
+            writeln!(
+                out,
+                "  Sign: commits={} tags={}",
+                profile.sign_commits, profile.sign_tags
+            )?;
+        }
+        if let Some(ref key) = profile.ssh_key {
+            if profile.agent {
+                writeln!(out, "  SSH Key: {} (ssh-agent)", key)?;
+            } else {
+                writeln!(out, "  SSH Key: {}", key)?;
+            }
+        }
+        for host in &profile.ssh_hosts {
+            writeln!(out, "  SSH Host: {} -> {}", host.alias, host.hostname)?;
+        }
+        if let Some(ref branch) = profile.default_branch {
+            writeln!(out, "  Default Branch: {}", branch)?;
+        }
+        if let Some(ref template) = profile.commit_template {
+            writeln!(out, "  Commit Template: {}", template)?;
+        }
+        for dir in &profile.directories {
+            writeln!(out, "  Directory: {}", dir)?;
+        }
+        for pattern in &profile.remote_patterns {
+            writeln!(out, "  Remote Pattern: {}", pattern)?;
+        }
+        for rule in &profile.url_rewrites {
+            let push = if rule.push_only { " (push)" } else { "" };
+            writeln!(out, "  URL Rewrite: {} -> {}{}", rule.from, rule.to, push)?;
+        }
+        for (key, values) in &profile.extra_config {
+            for value in values {
+                writeln!(out, "  {} = {}", key, value)?;
+            }
+        }
     }
     Ok(())
 }
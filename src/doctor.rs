@@ -0,0 +1,90 @@
+use crate::config::{self, Config, GitConfig};
+use crate::error::{LitError, Result};
+use crate::signing;
+use std::process::Command;
+
+/// Check that git is usable, the config file is valid and every profile's
+/// signing and SSH keys are available locally. Prints one line per check.
+pub fn doctor(config: &Config) -> Result<()> {
+    let mut problems = 0;
+    let mut report = |ok: bool, what: String| {
+        if ok {
+            println!("ok       {}", what);
+        } else {
+            problems += 1;
+            println!("problem  {}", what);
+        }
+    };
+
+    match Command::new("git").arg("--version").output() {
+        Ok(out) if out.status.success() => report(
+            true,
+            String::from_utf8_lossy(&out.stdout).trim().to_string(),
+        ),
+        _ => report(false, String::from("git is not installed or not on PATH")),
+    }
+
+    let config_problems = config::check_config()?;
+    report(
+        config_problems.is_empty(),
+        format!("config {}", config::config_path().display()),
+    );
+    for problem in config_problems {
+        println!("           {}", problem);
+    }
+
+    let mut names: Vec<&String> = config.profiles.keys().collect();
+    names.sort();
+    for name in names {
+        let profile = &config.profiles[name];
+        if let Some((ok, what)) = check_signing_key(profile) {
+            report(ok, format!("profile '{}': {}", name, what));
+        }
+        if let Some(ref key) = profile.ssh_key {
+            let path = config::expand_tilde(key);
+            report(
+                path.is_file(),
+                format!("profile '{}': SSH key {}", name, path.display()),
+            );
+        }
+    }
+
+    if problems > 0 {
+        return Err(LitError::InvalidConfig(format!(
+            "doctor found {} problem(s)",
+            problems
+        )));
+    }
+    Ok(())
+}
+
+/// Look up the profile's signing key. Returns `None` when the profile does
+/// not sign or uses a format lit cannot verify.
+fn check_signing_key(profile: &GitConfig) -> Option<(bool, String)> {
+    let key = profile.signing_key.as_deref()?;
+    match profile.gpg_format.as_deref() {
+        None | Some("openpgp") => {}
+        Some("ssh") => {
+            return Some(match signing::ssh_public_key(key) {
+                Ok(_) => (true, format!("SSH signing key {} readable", key)),
+                Err(e) => (false, format!("SSH signing key unusable: {}", e)),
+            })
+        }
+        _ => return None,
+    }
+    let found = Command::new("gpg")
+        .args(["--list-secret-keys", key])
+        .output()
+        .map(|out| out.status.success());
+    Some(match found {
+        Ok(true) => (true, format!("signing key {} found in keyring", key)),
+        Ok(false) => (
+            false,
+            format!("signing key {} not found by `gpg --list-secret-keys`", key),
+        ),
+        Err(e) => (
+            false,
+            format!("could not run gpg to check key {}: {}", key, e),
+        ),
+    })
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+use std::io;
+
+/// Errors surfaced by lit commands.
+///
+/// Each variant maps to a distinct process exit code so scripts wrapping lit
+/// can tell failures apart:
+///
+/// | code | variant          |
+/// |------|------------------|
+/// | 0    | success          |
+/// | 2    | usage error (reported by clap) |
+/// | 3    | `ProfileNotFound` |
+/// | 4    | `ProfileExists`   |
+/// | 5    | `GitFailed`       |
+/// | 6    | `Io`              |
+/// | 7    | `InvalidConfig`   |
+/// | 8    | `NoProfile`       |
+/// | 9    | `WrongIdentity`   |
+#[derive(Debug)]
+pub enum LitError {
+    ProfileNotFound(String),
+    ProfileExists(String),
+    GitFailed(String),
+    Io(io::Error),
+    InvalidConfig(String),
+    /// No profile was named and none could be chosen automatically.
+    NoProfile(String),
+    /// The identity git would use differs from the one the rules expect.
+    WrongIdentity(String),
+}
+
+pub type Result<T> = std::result::Result<T, LitError>;
+
+/// Exit code table shown in `lit --help`.
+pub const EXIT_CODES_HELP: &str = "Exit codes:
+  0  success
+  2  usage error
+  3  profile not found
+  4  profile already exists
+  5  git command failed
+  6  I/O error
+  7  invalid config
+  8  no profile applies
+  9  wrong identity for this repository";
+
+impl LitError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            LitError::ProfileNotFound(_) => 3,
+            LitError::ProfileExists(_) => 4,
+            LitError::GitFailed(_) => 5,
+            LitError::Io(_) => 6,
+            LitError::InvalidConfig(_) => 7,
+            LitError::NoProfile(_) => 8,
+            LitError::WrongIdentity(_) => 9,
+        }
+    }
+}
+
+impl fmt::Display for LitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LitError::ProfileNotFound(name) => write!(f, "Profile '{}' does not exist.", name),
+            LitError::ProfileExists(name) => write!(f, "Profile '{}' already exists.", name),
+            LitError::GitFailed(msg) => write!(f, "git failed: {}", msg),
+            LitError::Io(e) => write!(f, "{}", e),
+            LitError::InvalidConfig(msg) => write!(f, "invalid config: {}", msg),
+            LitError::NoProfile(msg) => write!(f, "no profile applies: {}", msg),
+            LitError::WrongIdentity(msg) => write!(f, "wrong identity: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for LitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LitError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LitError {
+    fn from(e: io::Error) -> Self {
+        LitError::Io(e)
+    }
+}
@@ -1,13 +1,560 @@
-use std::process::Command;
+use crate::config::{Config, DefaultScope, GitConfig};
+use crate::error::{LitError, Result};
+use crate::rules;
+use crate::ssh;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
 
-pub fn update_git_config(user_name: &str, user_email: &str) {
-    Command::new("git")
-        .args(&["config", "--global", "user.name", user_name])
-        .output()
-        .expect("Failed to update git user.name");
+/// Which git config file a profile is written to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scope {
+    Local,
+    Global,
+    Worktree,
+    System,
+    File(PathBuf),
+    /// The local config of the repository at a path, wherever its git
+    /// directory is.
+    LocalAt(PathBuf),
+}
+
+impl Scope {
+    /// Scopes offered by the TUI, in the order Tab cycles through them.
+    pub const CYCLE: [Scope; 4] = [Scope::Global, Scope::Local, Scope::Worktree, Scope::System];
+
+    /// Pick the scope for a switch: an explicit choice wins, otherwise the
+    /// configured default, where `local` only applies inside a repository.
+    pub fn resolve(explicit: Option<Scope>, config: &Config) -> Scope {
+        if let Some(scope) = explicit {
+            return scope;
+        }
+        if config.default_scope == DefaultScope::Local && inside_work_tree() {
+            Scope::Local
+        } else {
+            Scope::Global
+        }
+    }
 
+    fn args(&self) -> Vec<String> {
+        match self {
+            Scope::Local | Scope::LocalAt(_) => vec![String::from("--local")],
+            Scope::Global => vec![String::from("--global")],
+            Scope::Worktree => vec![String::from("--worktree")],
+            Scope::System => vec![String::from("--system")],
+            Scope::File(path) => vec![String::from("--file"), path.display().to_string()],
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Local => write!(f, "local"),
+            Scope::Global => write!(f, "global"),
+            Scope::Worktree => write!(f, "worktree"),
+            Scope::System => write!(f, "system"),
+            Scope::File(path) => write!(f, "file {}", path.display()),
+            Scope::LocalAt(dir) => write!(f, "local in {}", dir.display()),
+        }
+    }
+}
+
+fn inside_work_tree() -> bool {
+    git(&[
+        String::from("rev-parse"),
+        String::from("--is-inside-work-tree"),
+    ])
+    .map(|out| out == "true")
+    .unwrap_or(false)
+}
+
+fn run(args: &[String]) -> Result<Output> {
     Command::new("git")
-        .args(&["config", "--global", "user.email", user_email])
+        .args(args)
         .output()
-        .expect("Failed to update git user.email");
+        .map_err(|e| LitError::GitFailed(format!("could not run git: {}", e)))
+}
+
+fn failure(args: &[String], output: &Output) -> LitError {
+    LitError::GitFailed(format!(
+        "`git {}` exited with {}: {}",
+        args.join(" "),
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+/// Run `git` with `args` and return its trimmed stdout, failing with the
+/// command line and git's stderr when it exits non-zero.
+fn git(args: &[String]) -> Result<String> {
+    let output = run(args)?;
+    if !output.status.success() {
+        return Err(failure(args, &output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn config_args(scope: &Scope, rest: &[&str]) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Scope::LocalAt(dir) = scope {
+        args.push(String::from("-C"));
+        args.push(dir.display().to_string());
+    }
+    args.push(String::from("config"));
+    args.extend(scope.args());
+    args.extend(rest.iter().map(|s| s.to_string()));
+    args
+}
+
+/// Run `git` like [`git`], but treat exit status 1 (what `git config --get`
+/// returns for a missing key) as `None`.
+fn git_optional(args: &[String]) -> Result<Option<String>> {
+    let output = run(args)?;
+    match output.status.code() {
+        Some(0) => Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        )),
+        Some(1) => Ok(None),
+        _ => Err(failure(args, &output)),
+    }
+}
+
+/// Every value of `key` in `scope`, in file order.
+pub fn get_all(scope: &Scope, key: &str) -> Result<Vec<String>> {
+    Ok(git_optional(&config_args(scope, &["--get-all", key]))?
+        .map(|out| out.lines().map(String::from).collect())
+        .unwrap_or_default())
+}
+
+/// Replace every value of `key` in `scope` with `values`. An empty list
+/// removes the key.
+pub fn replace(scope: &Scope, key: &str, values: &[String]) -> Result<()> {
+    unset_all(scope, key)?;
+    add_all(scope, key, values)
+}
+
+fn unset_all(scope: &Scope, key: &str) -> Result<()> {
+    let args = config_args(scope, &["--unset-all", key]);
+    let output = run(&args)?;
+    // `git config --unset-all` exits 5 when the key is not set.
+    if !output.status.success() && output.status.code() != Some(5) {
+        return Err(failure(&args, &output));
+    }
+    Ok(())
+}
+
+fn add_all(scope: &Scope, key: &str, values: &[String]) -> Result<()> {
+    for value in values {
+        git(&config_args(scope, &["--add", key, value]))?;
+    }
+    Ok(())
+}
+
+/// The message of `e` without the `git failed:` prefix of `GitFailed`.
+fn git_message(e: &LitError) -> String {
+    match e {
+        LitError::GitFailed(msg) => msg.clone(),
+        e => e.to_string(),
+    }
+}
+
+/// A config value as git resolves it in the current directory.
+pub struct Effective {
+    pub value: String,
+    /// `local`, `global`, `system`, `worktree` or `command`.
+    pub scope: String,
+    /// Where git read it, e.g. `file:/home/me/.gitconfig`.
+    pub origin: String,
+}
+
+/// Resolve `key` across every config file git reads here, including
+/// `includeIf` fragments, and report which one won.
+pub fn effective(key: &str) -> Result<Option<Effective>> {
+    effective_at(Path::new("."), key)
+}
+
+/// Like [`effective`], for the repository or directory at `dir`.
+pub fn effective_at(dir: &Path, key: &str) -> Result<Option<Effective>> {
+    let mut args = vec![String::from("-C"), dir.display().to_string()];
+    args.extend(
+        ["config", "--show-scope", "--show-origin", "--get", key]
+            .iter()
+            .map(|s| s.to_string()),
+    );
+    let Some(line) = git_optional(&args)? else {
+        return Ok(None);
+    };
+    let mut parts = line.splitn(3, '\t');
+    let scope = parts.next().unwrap_or_default().to_string();
+    let origin = parts.next().unwrap_or_default().to_string();
+    let value = parts.next().unwrap_or_default().to_string();
+    Ok(Some(Effective {
+        value,
+        scope,
+        origin,
+    }))
+}
+
+/// The installed git's `(major, minor)` version.
+pub fn version() -> Result<(u32, u32)> {
+    let out = git(&[String::from("--version")])?;
+    let mut numbers = out
+        .split_whitespace()
+        .nth(2)
+        .unwrap_or_default()
+        .split('.')
+        .map(|n| n.parse::<u32>().unwrap_or(0));
+    Ok((numbers.next().unwrap_or(0), numbers.next().unwrap_or(0)))
+}
+
+/// The absolute git directory of the repository containing `dir`, or `None`
+/// outside a repository.
+pub fn git_dir(dir: &Path) -> Result<Option<PathBuf>> {
+    let args = [
+        String::from("-C"),
+        dir.display().to_string(),
+        String::from("rev-parse"),
+        String::from("--absolute-git-dir"),
+    ];
+    let output = run(&args)?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim(),
+    )))
+}
+
+/// The absolute git directory shared by every worktree of the repository
+/// containing `dir`, where its own hooks live.
+pub fn git_common_dir(dir: &Path) -> Result<PathBuf> {
+    let out = git(&[
+        String::from("-C"),
+        dir.display().to_string(),
+        String::from("rev-parse"),
+        String::from("--path-format=absolute"),
+        String::from("--git-common-dir"),
+    ])?;
+    Ok(PathBuf::from(out))
+}
+
+/// The work tree root of the repository containing `dir`, or `None` outside
+/// one.
+pub fn show_toplevel(dir: &Path) -> Result<Option<PathBuf>> {
+    let args = [
+        String::from("-C"),
+        dir.display().to_string(),
+        String::from("rev-parse"),
+        String::from("--show-toplevel"),
+    ];
+    let output = run(&args)?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim(),
+    )))
+}
+
+/// The branch checked out in the repository containing `dir`. `None` on a
+/// detached HEAD or outside a repository.
+pub fn current_branch(dir: &Path) -> Result<Option<String>> {
+    let args = [
+        String::from("-C"),
+        dir.display().to_string(),
+        String::from("symbolic-ref"),
+        String::from("--quiet"),
+        String::from("--short"),
+        String::from("HEAD"),
+    ];
+    let output = run(&args)?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(
+        String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    ))
+}
+
+/// Every `remote.<name>.url` of the repository at `dir`, as `(name, url)`.
+pub fn remote_urls(dir: &Path) -> Result<Vec<(String, String)>> {
+    let args = [
+        String::from("-C"),
+        dir.display().to_string(),
+        String::from("config"),
+        String::from("--get-regexp"),
+        String::from(r"^remote\..*\.url$"),
+    ];
+    let Some(out) = git_optional(&args)? else {
+        return Ok(Vec::new());
+    };
+    Ok(out
+        .lines()
+        .filter_map(|line| {
+            let (key, url) = line.split_once(' ')?;
+            let name = key.strip_prefix("remote.")?.strip_suffix(".url")?;
+            Some((name.to_string(), url.to_string()))
+        })
+        .collect())
+}
+
+/// Who wrote and who committed a commit.
+pub struct CommitIdentity {
+    pub short: String,
+    pub author_email: String,
+    pub committer_email: String,
+    pub subject: String,
+}
+
+/// Whether `rev` names a commit in the current repository.
+pub fn has_commit(rev: &str) -> Result<bool> {
+    let output = run(&[
+        String::from("cat-file"),
+        String::from("-e"),
+        format!("{}^{{commit}}", rev),
+    ])?;
+    Ok(output.status.success())
+}
+
+/// Commits selected by `revs` in `git rev-list` syntax, newest first.
+pub fn commit_identities(revs: &[String]) -> Result<Vec<CommitIdentity>> {
+    let mut args = vec![
+        String::from("log"),
+        String::from("--format=%h%x1f%ae%x1f%ce%x1f%s"),
+    ];
+    args.extend(revs.iter().cloned());
+    args.push(String::from("--"));
+    Ok(git(&args)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\x1f');
+            Some(CommitIdentity {
+                short: fields.next()?.to_string(),
+                author_email: fields.next()?.to_string(),
+                committer_email: fields.next()?.to_string(),
+                subject: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Key written to each scope lit touches, listing the extra, URL rewrite and
+/// tracked keys it set there so the next switch can remove the ones the new
+/// profile lacks.
+const MANAGED_KEY: &str = "lit.managed";
+
+/// Lowercase the section and variable name of `key`, which git compares
+/// case-insensitively, while keeping any subsection as written.
+pub fn normalize_key(key: &str) -> String {
+    match (key.find('.'), key.rfind('.')) {
+        (Some(first), Some(last)) => format!(
+            "{}{}{}",
+            key[..first].to_ascii_lowercase(),
+            &key[first..last],
+            key[last..].to_ascii_lowercase()
+        ),
+        _ => key.to_ascii_lowercase(),
+    }
+}
+
+/// The `url.<base>.insteadOf` keys of `profile`'s rewrite rules, with the
+/// prefixes each one replaces. Keys `extra_config` also sets are left to it.
+fn url_rewrite_entries(profile: &GitConfig) -> BTreeMap<String, Vec<String>> {
+    let mut entries: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for rule in &profile.url_rewrites {
+        let key = rule.key();
+        if !profile.extra_config.contains_key(&key) {
+            entries.entry(key).or_default().push(rule.from.clone());
+        }
+    }
+    entries
+}
+
+/// Keys lit writes only when the profile sets them, listed in `lit.managed`
+/// so a later switch removes only values lit wrote. Keys `extra_config`
+/// also sets are left to it.
+fn tracked_entries(profile: &GitConfig) -> Vec<(String, Vec<String>)> {
+    let tracked = [
+        ("init.defaultbranch", profile.default_branch.clone()),
+        ("commit.template", profile.commit_template.clone()),
+        (
+            "core.sshcommand",
+            profile.ssh_key.as_deref().map(ssh::ssh_command),
+        ),
+    ];
+    tracked
+        .into_iter()
+        .filter(|(key, _)| !profile.extra_config.contains_key(*key))
+        .filter_map(|(key, value)| Some((key.to_string(), vec![value?])))
+        .collect()
+}
+
+/// Every key and value `profile` writes, identity first. Signing keys the
+/// profile does not use come with no values so applying it unsets them;
+/// `extra_config` takes precedence over them.
+pub fn profile_entries(profile: &GitConfig) -> Vec<(String, Vec<String>)> {
+    let mut entries = vec![
+        (String::from("user.name"), vec![profile.user_name.clone()]),
+        (String::from("user.email"), vec![profile.user_email.clone()]),
+    ];
+    entries.extend(
+        profile
+            .extra_config
+            .iter()
+            .map(|(key, values)| (key.clone(), values.clone())),
+    );
+    entries.extend(url_rewrite_entries(profile));
+
+    let flag = |on: bool| {
+        if on {
+            vec![String::from("true")]
+        } else {
+            Vec::new()
+        }
+    };
+    let owned = [
+        (
+            "user.signingkey",
+            profile.signing_key.iter().cloned().collect(),
+        ),
+        ("gpg.format", profile.gpg_format.iter().cloned().collect()),
+        ("commit.gpgsign", flag(profile.sign_commits)),
+        ("tag.gpgsign", flag(profile.sign_tags)),
+    ];
+    for (key, values) in owned {
+        if !profile.extra_config.contains_key(key) {
+            entries.push((key.to_string(), values));
+        }
+    }
+    entries.extend(tracked_entries(profile));
+    entries
+}
+
+/// Write `profile` to `scope`, removing extra keys, URL rewrites and
+/// tracked keys a previous switch set there that this profile does not have.
+pub fn update_git_config(scope: &Scope, profile: &GitConfig) -> Result<()> {
+    let mut changes = profile_entries(profile);
+    let managed: Vec<String> = profile
+        .extra_config
+        .keys()
+        .cloned()
+        .chain(url_rewrite_entries(profile).into_keys())
+        .chain(tracked_entries(profile).into_iter().map(|(key, _)| key))
+        .collect();
+    for key in get_all(scope, MANAGED_KEY)? {
+        if !changes.iter().any(|(k, _)| *k == key) {
+            changes.push((key, Vec::new()));
+        }
+    }
+    changes.push((String::from(MANAGED_KEY), managed));
+    apply(scope, &changes)?;
+    if *scope == Scope::Global {
+        // git appends new sections, which would come after lit's includes.
+        rules::keep_includes_last()?;
+    }
+    Ok(())
+}
+
+/// Write `changes` in order. If any write fails, every key already touched
+/// is put back, so the scope is never left half-switched.
+fn apply(scope: &Scope, changes: &[(String, Vec<String>)]) -> Result<()> {
+    let mut touched: Vec<(&str, Vec<String>)> = Vec::new();
+    for (key, values) in changes {
+        let result = get_all(scope, key).and_then(|before| {
+            if before == *values {
+                return Ok(());
+            }
+            unset_all(scope, key)?;
+            // Only a key whose old values are gone needs putting back.
+            touched.push((key, before));
+            add_all(scope, key, values)
+        });
+        if let Err(e) = result {
+            for (key, before) in touched.iter().rev() {
+                if let Err(rollback) = replace(scope, key, before) {
+                    return Err(LitError::GitFailed(format!(
+                        "{}; rolling back {} also failed: {}",
+                        git_message(&e),
+                        key,
+                        git_message(&rollback)
+                    )));
+                }
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn scratch(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("lit-git-config-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        let mut lock = path.clone().into_os_string();
+        lock.push(".lock");
+        let _ = fs::remove_file(lock);
+        path
+    }
+
+    fn changes(entries: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.iter().map(|s| s.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn a_failure_partway_restores_the_keys_already_written() {
+        let path = scratch("partway");
+        let scope = Scope::File(path.clone());
+        replace(&scope, "user.name", &[String::from("Old")]).unwrap();
+        replace(&scope, "user.email", &[String::from("old@x.org")]).unwrap();
+
+        // A key without a section is rejected by git after the first two
+        // keys have been written.
+        let err = apply(
+            &scope,
+            &changes(&[
+                ("user.name", &["New"]),
+                ("user.email", &["new@x.org"]),
+                ("nosection", &["x"]),
+            ]),
+        )
+        .unwrap_err();
+        assert!(!err.to_string().contains("rolling back"), "{}", err);
+        assert_eq!(get_all(&scope, "user.name").unwrap(), ["Old"]);
+        assert_eq!(get_all(&scope, "user.email").unwrap(), ["old@x.org"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_locked_file_reports_one_error_and_changes_nothing() {
+        let path = scratch("locked");
+        let scope = Scope::File(path.clone());
+        replace(&scope, "user.name", &[String::from("Old")]).unwrap();
+        let mut lock = path.clone().into_os_string();
+        lock.push(".lock");
+        fs::write(&lock, "").unwrap();
+
+        let err = apply(&scope, &changes(&[("user.name", &["New"])])).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("git failed: `git config"),
+            "{}",
+            message
+        );
+        assert_eq!(message.matches("git failed").count(), 1, "{}", message);
+        assert!(!message.contains("rolling back"), "{}", message);
+
+        fs::remove_file(&lock).unwrap();
+        assert_eq!(get_all(&scope, "user.name").unwrap(), ["Old"]);
+        fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,267 @@
+use crate::config;
+use crate::error::{LitError, Result};
+use crate::git_config::{self, Scope};
+use crate::managed;
+use crate::ssh;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Line every hook lit writes carries, so reinstalling replaces it instead of
+/// chaining to it.
+const MARKER: &str = "# lit-hook (generated by lit, edits will be lost)";
+
+/// Suffix of a hook lit moved aside to run before its own.
+const CHAINED_SUFFIX: &str = ".lit-chained";
+
+/// Every hook githooks(5) documents, forwarded to the repository's own hooks
+/// when `lit hooks install --global` points `core.hooksPath` at lit's
+/// directory. Repository hooks under other names stop running; `install`
+/// warns about the ones it finds.
+const GIT_HOOKS: &[&str] = &[
+    "applypatch-msg",
+    "pre-applypatch",
+    "post-applypatch",
+    "pre-commit",
+    "pre-merge-commit",
+    "prepare-commit-msg",
+    "commit-msg",
+    "post-commit",
+    "pre-rebase",
+    "post-checkout",
+    "post-merge",
+    "pre-push",
+    "pre-receive",
+    "update",
+    "proc-receive",
+    "post-receive",
+    "post-update",
+    "reference-transaction",
+    "push-to-checkout",
+    "pre-auto-gc",
+    "post-rewrite",
+    "sendemail-validate",
+    "p4-changelist",
+    "p4-prepare-changelist",
+    "p4-post-changelist",
+    "p4-pre-submit",
+    "post-index-change",
+];
+
+/// Directory lit owns for global hooks, next to the config file.
+fn global_hooks_dir() -> PathBuf {
+    config::config_path().with_file_name("hooks")
+}
+
+/// Whether `path` is a hook lit wrote.
+fn is_ours(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|s| s.lines().any(|l| l == MARKER))
+}
+
+/// The command a hook runs to call this lit binary.
+fn lit_command() -> Result<String> {
+    let exe = env::current_exe()?;
+    Ok(ssh::shell_quote(&exe.display().to_string()))
+}
+
+/// Hooks git feeds on stdin, which every chained hook must see again.
+fn reads_stdin(name: &str) -> bool {
+    matches!(name, "pre-push" | "post-rewrite" | "reference-transaction")
+}
+
+/// A hook that runs each of `chained` that exists, stopping at the first
+/// failure, then `lit <check>` with the hook's arguments.
+fn render_hook(name: &str, chained: &[String], check: &str) -> Result<String> {
+    let lit = lit_command()?;
+    let mut out = format!("#!/bin/sh\n{}\n", MARKER);
+    if !reads_stdin(name) {
+        for hook in chained {
+            out.push_str(&format!(
+                "if [ -x {hook} ]; then {hook} \"$@\" || exit $?; fi\n",
+                hook = hook
+            ));
+        }
+        out.push_str(&format!("exec {} {} \"$@\"\n", lit, check));
+        return Ok(out);
+    }
+    out.push_str("input=$(mktemp) || exit 1\ntrap 'rm -f \"$input\"' EXIT\ncat >\"$input\"\n");
+    for hook in chained {
+        out.push_str(&format!(
+            "if [ -x {hook} ]; then {hook} \"$@\" <\"$input\" || exit $?; fi\n",
+            hook = hook
+        ));
+    }
+    out.push_str(&format!("{} {} \"$@\" <\"$input\"\n", lit, check));
+    Ok(out)
+}
+
+/// A hook that only runs the repository's own hook of the same name, which
+/// a global `core.hooksPath` would otherwise hide.
+fn render_forward() -> String {
+    format!(
+        "#!/bin/sh\n{}\n\
+         hook=\"$(git rev-parse --git-common-dir)/hooks/$(basename \"$0\")\"\n\
+         if [ -x \"$hook\" ]; then exec \"$hook\" \"$@\"; fi\n",
+        MARKER
+    )
+}
+
+fn write_hook(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)?;
+    managed::set_private(path, 0o755)?;
+    Ok(())
+}
+
+/// Install the `name` hook running `lit <check>` in `dir`. A hook of that
+/// name not written by lit is moved aside and runs first. Returns what was
+/// done, one line per change.
+fn install_in(dir: &Path, name: &str, check: &str, extra: &[String]) -> Result<Vec<String>> {
+    let path = dir.join(name);
+    let mut aside = path.clone().into_os_string();
+    aside.push(CHAINED_SUFFIX);
+    let aside = PathBuf::from(aside);
+
+    let mut done = Vec::new();
+    match fs::symlink_metadata(&path) {
+        Ok(_) if !is_ours(&path) => {
+            if aside.exists() {
+                return Err(LitError::Io(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} exists and {} is in the way; move one of them",
+                        path.display(),
+                        aside.display()
+                    ),
+                )));
+            }
+            fs::rename(&path, &aside)?;
+            done.push(format!(
+                "Moved the existing hook to {}; it runs first.",
+                aside.display()
+            ));
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut chained = vec![format!(
+        "\"$(dirname \"$0\")\"/{}",
+        ssh::shell_quote(&format!("{}{}", name, CHAINED_SUFFIX))
+    )];
+    chained.extend(extra.iter().cloned());
+    write_hook(&path, &render_hook(name, &chained, check)?)?;
+    done.push(format!("Installed {}.", path.display()));
+    Ok(done)
+}
+
+/// Warn about hooks of the current repository, if any, that git stops
+/// running once `core.hooksPath` points at lit's directory.
+fn warn_unforwarded() -> Result<()> {
+    let here = Path::new(".");
+    if git_config::show_toplevel(here)?.is_none() {
+        return Ok(());
+    }
+    let dir = git_config::git_common_dir(here)?.join("hooks");
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.ends_with(".sample") || name.ends_with(CHAINED_SUFFIX) {
+            continue;
+        }
+        if !GIT_HOOKS.contains(&name.as_str()) {
+            eprintln!(
+                "warning: {} is not a hook lit forwards and stops running with the global core.hooksPath",
+                dir.join(&name).display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Install the `name` hook running `lit <check>`. For the current
+/// repository it goes to its own hooks directory, refusing when lit's
+/// global hook already runs the check there. With `global` it goes to the global `core.hooksPath`
+/// directory; when none is set lit points it at its own directory, with
+/// every other client hook forwarding to the repository's own.
+pub fn install(name: &str, check: &str, global: bool) -> Result<Vec<String>> {
+    if !global {
+        if git_config::show_toplevel(Path::new("."))?.is_none() {
+            return Err(LitError::GitFailed(String::from(
+                "not inside a git repository; pass --global to install for every repository",
+            )));
+        }
+        let own = global_hooks_dir();
+        let dir = git_config::git_common_dir(Path::new("."))?.join("hooks");
+        let hooks_path =
+            git_config::effective("core.hooksPath")?.map(|e| config::expand_tilde(&e.value));
+        match hooks_path {
+            // lit's forwarding hooks run the repository's own, except the
+            // one lit installed there, which already runs the check.
+            Some(path) if path == own => {
+                let global = own.join(name);
+                if !global.exists() {
+                    eprintln!(
+                        "warning: core.hooksPath is {} and has no {} hook; git will not run {}",
+                        own.display(),
+                        name,
+                        dir.join(name).display()
+                    );
+                } else if fs::read_to_string(&global).ok() != Some(render_forward()) {
+                    return Err(LitError::Io(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!(
+                            "{} already runs the check in every repository",
+                            global.display()
+                        ),
+                    )));
+                }
+            }
+            Some(path) => eprintln!(
+                "warning: core.hooksPath is {}, so git will not run {}",
+                path.display(),
+                dir.join(name).display()
+            ),
+            None => {}
+        }
+        return install_in(&dir, name, check, &[]);
+    }
+
+    let own = global_hooks_dir();
+    let current = git_config::get_all(&Scope::Global, "core.hooksPath")?
+        .pop()
+        .map(|dir| config::expand_tilde(&dir));
+    if let Some(dir) = current.filter(|dir| *dir != own) {
+        return install_in(&dir, name, check, &[]);
+    }
+
+    warn_unforwarded()?;
+    let mut done = Vec::new();
+    for hook in GIT_HOOKS.iter().filter(|h| **h != name) {
+        let path = own.join(hook);
+        if !path.exists() {
+            write_hook(&path, &render_forward())?;
+        }
+    }
+    let repo_hook = format!(
+        "\"$(git rev-parse --git-common-dir)\"/hooks/{}",
+        ssh::shell_quote(name)
+    );
+    done.extend(install_in(&own, name, check, &[repo_hook])?);
+    git_config::replace(
+        &Scope::Global,
+        "core.hooksPath",
+        &[own.display().to_string()],
+    )?;
+    done.push(format!(
+        "Set the global core.hooksPath to {}; repositories' own hooks still run.",
+        own.display()
+    ));
+    Ok(done)
+}
@@ -0,0 +1,403 @@
+use crate::config::Config;
+use crate::error::{LitError, Result};
+use crate::git_config::{self, CommitIdentity, Effective};
+use crate::resolve::{self, RepoFacts, Resolution, Source};
+use crate::ssh;
+use std::collections::HashSet;
+use std::env;
+use std::io::BufRead;
+use std::path::Path;
+
+/// The identity git will use for commits in the current directory.
+pub struct Identity {
+    pub name: Option<Effective>,
+    pub email: Option<Effective>,
+}
+
+pub fn effective_identity() -> Result<Identity> {
+    Ok(Identity {
+        name: git_config::effective("user.name")?,
+        email: git_config::effective("user.email")?,
+    })
+}
+
+/// Name of the profile whose email (and name, when set) matches `identity`.
+pub fn matching_profile<'a>(config: &'a Config, identity: &Identity) -> Option<&'a str> {
+    let email = identity.email.as_ref()?;
+    let mut candidates: Vec<_> = config
+        .profiles
+        .iter()
+        .filter(|(_, p)| p.user_email.eq_ignore_ascii_case(&email.value))
+        .collect();
+    candidates.sort_by_key(|(name, _)| name.as_str());
+    let by_name = candidates.iter().find(|(_, p)| {
+        identity
+            .name
+            .as_ref()
+            .is_some_and(|n| n.value == p.user_name)
+    });
+    by_name
+        .or(candidates.first())
+        .map(|(name, _)| name.as_str())
+}
+
+/// Print the effective identity and warn about mismatches with the profiles.
+pub fn whoami(config: &Config) -> Result<()> {
+    let identity = effective_identity()?;
+    for (label, value) in [
+        ("user.name", &identity.name),
+        ("user.email", &identity.email),
+    ] {
+        match value {
+            Some(v) => println!("{}: {} ({}, {})", label, v.value, v.scope, v.origin),
+            None => println!("{}: (unset)", label),
+        }
+    }
+
+    println!("ssh key: {}", push_key()?);
+
+    let facts = RepoFacts::of(Path::new("."))?;
+    if let Some(ref pin) = facts.pin {
+        println!("pinned: {} ({})", pin.value, pin.path.display());
+        let email = identity.email.as_ref().map(|e| e.value.as_str());
+        match pin.expected_email(config) {
+            Some(ref expected) if email.is_none_or(|e| !e.eq_ignore_ascii_case(expected)) => {
+                let fix = if pin.profile(config).is_ok() {
+                    "; run 'lit apply' to fix"
+                } else {
+                    ""
+                };
+                eprintln!(
+                    "warning: {} expects {} but user.email is {}{}",
+                    pin.path.display(),
+                    expected,
+                    email.unwrap_or("unset"),
+                    fix
+                )
+            }
+            _ => {}
+        }
+    }
+    let expected = match resolve::resolve(config, &facts) {
+        Ok(expected) => expected,
+        Err(e) => {
+            eprintln!("warning: {}", e);
+            None
+        }
+    };
+    match matching_profile(config, &identity) {
+        Some(name) => {
+            println!("profile: {}", name);
+            match expected {
+                // A disagreement with the pin was reported above.
+                Some(Resolution {
+                    source: Source::Pin(_),
+                    ..
+                }) => {}
+                Some(ref r) if r.profile != name && matches!(r.source, Source::Current) => {
+                    eprintln!(
+                        "warning: effective identity matches profile '{}' but the current profile is '{}'",
+                        name, r.profile
+                    )
+                }
+                Some(ref r) if r.profile != name => eprintln!(
+                    "warning: effective identity matches profile '{}' but the rules pick '{}' ({})",
+                    name,
+                    r.profile,
+                    r.reason()
+                ),
+                _ => {}
+            }
+        }
+        None => {
+            println!("profile: (none)");
+            if identity.email.is_some() {
+                eprintln!("warning: effective identity does not match any profile");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Describe the SSH key git will push with here: `GIT_SSH_COMMAND` wins over
+/// `core.sshCommand`, and without either ssh picks keys itself.
+fn push_key() -> Result<String> {
+    if let Ok(command) = env::var("GIT_SSH_COMMAND") {
+        return Ok(match ssh::identity_file(&command) {
+            Some(key) => format!("{} (GIT_SSH_COMMAND)", key),
+            None => format!("ssh defaults (GIT_SSH_COMMAND is '{}')", command),
+        });
+    }
+    Ok(match git_config::effective("core.sshCommand")? {
+        Some(command) => match ssh::identity_file(&command.value) {
+            Some(key) => format!("{} ({}, {})", key, command.scope, command.origin),
+            None => format!(
+                "ssh defaults (core.sshCommand is '{}', {})",
+                command.value, command.origin
+            ),
+        },
+        None => String::from("ssh defaults (ssh-agent and ~/.ssh/config)"),
+    })
+}
+
+/// Fail when the email git would commit with here differs from the one of
+/// the profile the pin file or rules pick, or from the email the pin file
+/// names. Passes when neither picks one.
+pub fn check_identity(config: &Config) -> Result<()> {
+    let here = Path::new(".");
+    if git_config::show_toplevel(here)?.is_none() {
+        return Err(LitError::GitFailed(String::from(
+            "not inside a git repository",
+        )));
+    }
+    let facts = RepoFacts::of(here)?;
+    let email = git_config::effective("user.email")?.map(|e| e.value);
+    // A pinned email is the identity itself; no profile needs to have it.
+    if let Some(pin) = facts.pin.as_ref().filter(|pin| pin.is_email()) {
+        if email
+            .as_deref()
+            .is_some_and(|e| e.eq_ignore_ascii_case(&pin.value))
+        {
+            return Ok(());
+        }
+        let fix = if pin.profile(config).is_ok() {
+            "Run 'lit apply' to fix it"
+        } else {
+            "Set user.email to it"
+        };
+        return Err(LitError::WrongIdentity(format!(
+            "user.email is {} but {} pins {}.\n\
+             {}, or commit with --no-verify to skip this check.",
+            email.as_deref().unwrap_or("unset"),
+            pin.path.display(),
+            pin.value,
+            fix
+        )));
+    }
+    let expected = match resolve::resolve(config, &facts)? {
+        // The current profile is only a fallback; a repository switched
+        // with --local deliberately differs from it.
+        None
+        | Some(Resolution {
+            source: Source::Current,
+            ..
+        }) => return Ok(()),
+        Some(expected) => expected,
+    };
+    let Some(profile) = config.profiles.get(&expected.profile) else {
+        return Err(LitError::ProfileNotFound(expected.profile));
+    };
+    if email
+        .as_deref()
+        .is_some_and(|e| e.eq_ignore_ascii_case(&profile.user_email))
+    {
+        return Ok(());
+    }
+    Err(LitError::WrongIdentity(format!(
+        "user.email is {} but profile '{}' expects {} ({}).\n\
+         Run 'lit apply' to fix it, or commit with --no-verify to skip this check.",
+        email.as_deref().unwrap_or("unset"),
+        expected.profile,
+        profile.user_email,
+        expected.reason()
+    )))
+}
+
+/// The `git rev-list` arguments selecting what one line of pre-push input
+/// sends, or `None` for a deletion or a malformed line. Commits already on
+/// some remote are left out, and so is the remote's old tip when `known`
+/// finds it here.
+fn outgoing_revs(line: &str, known: impl Fn(&str) -> Result<bool>) -> Result<Option<Vec<String>>> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [_, local_sha, _, remote_sha] = fields[..] else {
+        return Ok(None);
+    };
+    let zero = |sha: &str| sha.bytes().all(|b| b == b'0');
+    if zero(local_sha) {
+        return Ok(None);
+    }
+    let mut revs = vec![local_sha.to_string()];
+    if !zero(remote_sha) && known(remote_sha)? {
+        revs.push(format!("^{}", remote_sha));
+    }
+    revs.push(String::from("--not"));
+    revs.push(String::from("--remotes"));
+    Ok(Some(revs))
+}
+
+/// A report line for each of `commits` authored or committed with an email
+/// other than `email`, skipping short hashes already in `seen`.
+fn wrong_identities(
+    commits: Vec<CommitIdentity>,
+    email: &str,
+    seen: &mut HashSet<String>,
+) -> Vec<String> {
+    let allowed = |e: &str| e.eq_ignore_ascii_case(email);
+    let mut out = Vec::new();
+    for commit in commits {
+        if !seen.insert(commit.short.clone()) {
+            continue;
+        }
+        let mut wrong = Vec::new();
+        if !allowed(&commit.author_email) {
+            wrong.push(format!("author {}", commit.author_email));
+        }
+        if !allowed(&commit.committer_email) {
+            wrong.push(format!("committer {}", commit.committer_email));
+        }
+        if !wrong.is_empty() {
+            out.push(format!(
+                "  {}  {}  {}",
+                commit.short,
+                wrong.join(", "),
+                commit.subject
+            ));
+        }
+    }
+    out
+}
+
+/// Fail when a commit about to be pushed to `url` has an author or committer
+/// email other than the one of the profile the pin file or rules pick for
+/// that remote, by its configured URLs and the URL pushed to. Passes when
+/// neither picks one. `input` is what git passes a pre-push hook on stdin.
+pub fn check_push(config: &Config, remote: &str, url: &str, input: impl BufRead) -> Result<()> {
+    let here = Path::new(".");
+    let mut facts = RepoFacts::of(here)?;
+    facts.remote_urls = git_config::remote_urls(here)?
+        .into_iter()
+        .filter(|(name, _)| name == remote)
+        .map(|(_, url)| url)
+        .collect();
+    facts.remote_urls.push(url.to_string());
+    // A pinned email is the identity itself; no profile needs to have it.
+    let (email, expected) = match facts.pin {
+        Some(ref pin) if pin.is_email() => (
+            pin.value.clone(),
+            format!("<{}> (pinned by {})", pin.value, pin.path.display()),
+        ),
+        _ => match resolve::resolve(config, &facts)? {
+            None
+            | Some(Resolution {
+                source: Source::Current,
+                ..
+            }) => return Ok(()),
+            Some(expected) => {
+                let Some(profile) = config.profiles.get(&expected.profile) else {
+                    return Err(LitError::ProfileNotFound(expected.profile));
+                };
+                (
+                    profile.user_email.clone(),
+                    format!(
+                        "profile '{}' <{}> ({})",
+                        expected.profile,
+                        profile.user_email,
+                        expected.reason()
+                    ),
+                )
+            }
+        },
+    };
+
+    let mut seen = HashSet::new();
+    let mut offending = Vec::new();
+    for line in input.lines() {
+        let Some(revs) = outgoing_revs(&line?, git_config::has_commit)? else {
+            continue;
+        };
+        let commits = git_config::commit_identities(&revs)?;
+        offending.extend(wrong_identities(commits, &email, &mut seen));
+    }
+    if offending.is_empty() {
+        return Ok(());
+    }
+    Err(LitError::WrongIdentity(format!(
+        "{} commit(s) pushed to {} are not by {}:\n{}\n\
+         Run 'lit apply', then rewrite them with \
+         'git rebase --exec \"git commit --amend --no-edit --reset-author\" <base>', \
+         or push with --no-verify to skip this check.",
+        offending.len(),
+        remote,
+        expected,
+        offending.join("\n")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZERO: &str = "0000000000000000000000000000000000000000";
+    const LOCAL: &str = "1111111111111111111111111111111111111111";
+    const REMOTE: &str = "2222222222222222222222222222222222222222";
+
+    fn revs(line: &str, known: bool) -> Option<Vec<String>> {
+        outgoing_revs(line, |_| Ok(known)).unwrap()
+    }
+
+    fn commit(short: &str, author: &str, committer: &str) -> CommitIdentity {
+        CommitIdentity {
+            short: short.to_string(),
+            author_email: author.to_string(),
+            committer_email: committer.to_string(),
+            subject: format!("subject {}", short),
+        }
+    }
+
+    #[test]
+    fn a_deleted_ref_sends_nothing() {
+        let line = format!("(delete) {} refs/heads/old {}", ZERO, REMOTE);
+        assert_eq!(revs(&line, true), None);
+        assert_eq!(revs("", true), None);
+        assert_eq!(revs("refs/heads/a only-two", true), None);
+    }
+
+    #[test]
+    fn a_new_branch_sends_what_no_remote_has() {
+        let line = format!("refs/heads/new {} refs/heads/new {}", LOCAL, ZERO);
+        assert_eq!(revs(&line, true).unwrap(), [LOCAL, "--not", "--remotes"]);
+    }
+
+    #[test]
+    fn an_update_leaves_out_the_old_tip_when_known() {
+        let line = format!("refs/heads/main {} refs/heads/main {}", LOCAL, REMOTE);
+        assert_eq!(
+            revs(&line, true).unwrap(),
+            [
+                LOCAL.to_string(),
+                format!("^{}", REMOTE),
+                "--not".into(),
+                "--remotes".into()
+            ]
+        );
+        assert_eq!(revs(&line, false).unwrap(), [LOCAL, "--not", "--remotes"]);
+    }
+
+    #[test]
+    fn the_report_names_each_wrong_commit_once() {
+        let mut seen = HashSet::new();
+        let first = wrong_identities(
+            vec![
+                commit("abc1234", "w@x.org", "w@x.org"),
+                commit("def5678", "h@x.org", "W@X.org"),
+                commit("0123abc", "h@x.org", "h@x.org"),
+            ],
+            "w@x.org",
+            &mut seen,
+        );
+        assert_eq!(
+            first,
+            [
+                "  def5678  author h@x.org  subject def5678",
+                "  0123abc  author h@x.org, committer h@x.org  subject 0123abc",
+            ]
+        );
+        // A commit sent for a second ref is reported once.
+        let again = wrong_identities(
+            vec![commit("def5678", "h@x.org", "h@x.org")],
+            "w@x.org",
+            &mut seen,
+        );
+        assert!(again.is_empty());
+    }
+}
@@ -1,8 +1,10 @@
 use crossterm::event::{KeyCode, KeyEvent};
-use std::io;
 use tui::widgets::ListState;
 
-use crate::config::{save_config, Config};
+use crate::config::{Config, GitConfig};
+use crate::error::{LitError, Result};
+use crate::git_config::Scope;
+use crate::profile::{self, ProfileUpdate};
 
 #[derive(PartialEq)]
 pub enum InputMode {
@@ -12,13 +14,17 @@ pub enum InputMode {
     InputUserEmail,
     UpdateProfileUserName,
     UpdateProfileUserEmail,
+    EditExtraConfig,
+    ConfirmGenerateSshKey,
     ListingProfiles,
     DeleteProfile,
     SwitchProfile,
+    SwitchProfileFile,
     UpdateProfile,
     ConfirmDeleteProfile,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn handle_input(
     key: KeyEvent,
     input_mode: &mut InputMode,
@@ -30,14 +36,19 @@ pub fn handle_input(
     selected_profile_to_delete: &mut Option<String>,
     delete_state: &mut ListState,
     delete_options: &[String],
-    _selected_profile_to_switch: &mut Option<String>,
+    selected_profile_to_switch: &mut Option<String>,
     switch_state: &mut ListState,
     switch_options: &[String],
+    switch_scope: &mut Option<Scope>,
+    scope_file: &mut String,
+    editing_profile: &mut Option<String>,
+    extra_input: &mut String,
+    pending_keygen: &mut Option<String>,
     selected_profile_to_update: &mut Option<String>,
     update_state: &mut ListState,
     update_options: &[String],
     config: &mut Config,
-) -> io::Result<()> {
+) -> Result<()> {
     match *input_mode {
         InputMode::Normal => match key.code {
             KeyCode::Up | KeyCode::Char('k') => {
@@ -69,10 +80,11 @@ pub fn handle_input(
             KeyCode::Backspace => {
                 profile_name.pop();
             }
-            KeyCode::Enter => {
-                if !profile_name.is_empty() {
-                    *input_mode = InputMode::InputUserName;
+            KeyCode::Enter if !profile_name.is_empty() => {
+                if config.profiles.contains_key(profile_name.as_str()) {
+                    return Err(LitError::ProfileExists(profile_name.clone()));
                 }
+                *input_mode = InputMode::InputUserName;
             }
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
@@ -87,10 +99,8 @@ pub fn handle_input(
             KeyCode::Backspace => {
                 user_name.pop();
             }
-            KeyCode::Enter => {
-                if !user_name.is_empty() {
-                    *input_mode = InputMode::InputUserEmail;
-                }
+            KeyCode::Enter if !user_name.is_empty() => {
+                *input_mode = InputMode::InputUserEmail;
             }
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
@@ -106,22 +116,22 @@ pub fn handle_input(
             KeyCode::Backspace => {
                 user_email.pop();
             }
-            KeyCode::Enter => {
-                if !user_email.is_empty() {
-                    // Add profile to config
-                    config.profiles.insert(
-                        profile_name.clone(),
-                        crate::config::GitConfig {
-                            user_name: user_name.clone(),
-                            user_email: user_email.clone(),
-                        },
-                    );
-                    save_config(config);
-                    *input_mode = InputMode::Normal;
-                    profile_name.clear();
-                    user_name.clear();
-                    user_email.clear();
-                }
+            KeyCode::Enter if !user_email.is_empty() => {
+                // Add profile to config
+                profile::add_profile(
+                    config,
+                    profile_name,
+                    GitConfig {
+                        user_name: user_name.clone(),
+                        user_email: user_email.clone(),
+                        ..Default::default()
+                    },
+                )?;
+                *editing_profile = Some(profile_name.clone());
+                *input_mode = InputMode::ConfirmGenerateSshKey;
+                profile_name.clear();
+                user_name.clear();
+                user_email.clear();
             }
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
@@ -138,10 +148,8 @@ pub fn handle_input(
             KeyCode::Backspace => {
                 user_name.pop();
             }
-            KeyCode::Enter => {
-                if !user_name.is_empty() {
-                    *input_mode = InputMode::UpdateProfileUserEmail;
-                }
+            KeyCode::Enter if !user_name.is_empty() => {
+                *input_mode = InputMode::UpdateProfileUserEmail;
             }
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
@@ -156,20 +164,22 @@ pub fn handle_input(
             KeyCode::Backspace => {
                 user_email.pop();
             }
-            KeyCode::Enter => {
-                if !user_email.is_empty() {
-                    if let Some(profile) = selected_profile_to_update.clone() {
-                        if let Some(profile_data) = config.profiles.get_mut(&profile) {
-                            profile_data.user_name = user_name.clone();
-                            profile_data.user_email = user_email.clone();
-                            save_config(config);
-                        }
-                    }
-                    selected_profile_to_update.take();
-                    user_name.clear();
-                    user_email.clear();
+            KeyCode::Enter if !user_email.is_empty() => {
+                if let Some(profile) = selected_profile_to_update.clone() {
+                    let update = ProfileUpdate {
+                        user_name: Some(user_name.clone()),
+                        user_email: Some(user_email.clone()),
+                        ..Default::default()
+                    };
+                    profile::update_profile(config, &profile, &update)?;
+                    *editing_profile = Some(profile);
+                    *input_mode = InputMode::EditExtraConfig;
+                } else {
                     *input_mode = InputMode::Normal;
                 }
+                selected_profile_to_update.take();
+                user_name.clear();
+                user_email.clear();
             }
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
@@ -177,6 +187,41 @@ pub fn handle_input(
             }
             _ => {}
         },
+        InputMode::ConfirmGenerateSshKey => match key.code {
+            KeyCode::Char('y') => {
+                *pending_keygen = editing_profile.clone();
+                *input_mode = InputMode::EditExtraConfig;
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                *input_mode = InputMode::EditExtraConfig;
+            }
+            _ => {}
+        },
+        InputMode::EditExtraConfig => match key.code {
+            KeyCode::Char(c) => {
+                extra_input.push(c);
+            }
+            KeyCode::Backspace => {
+                extra_input.pop();
+            }
+            KeyCode::Enter if extra_input.is_empty() => {
+                editing_profile.take();
+                *input_mode = InputMode::Normal;
+            }
+            KeyCode::Enter => {
+                if let Some(ref profile) = editing_profile {
+                    let update = parse_extra_input(extra_input)?;
+                    profile::update_profile(config, profile, &update)?;
+                }
+                extra_input.clear();
+            }
+            KeyCode::Esc => {
+                editing_profile.take();
+                extra_input.clear();
+                *input_mode = InputMode::Normal;
+            }
+            _ => {}
+        },
         InputMode::ListingProfiles => match key.code {
             KeyCode::Char('b') | KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
@@ -220,17 +265,22 @@ pub fn handle_input(
                     switch_state.select(Some(i + 1));
                 }
             }
+            KeyCode::Tab => {
+                *switch_scope = next_scope(switch_scope);
+            }
+            KeyCode::Char('f') => {
+                if let Some(i) = switch_state.selected() {
+                    *selected_profile_to_switch = Some(switch_options[i].clone());
+                    scope_file.clear();
+                    *input_mode = InputMode::SwitchProfileFile;
+                }
+            }
             KeyCode::Enter => {
                 if let Some(i) = switch_state.selected() {
                     let selected_profile = &switch_options[i];
-                    if config.profiles.contains_key(selected_profile) {
-                        let profile = config.profiles.get(selected_profile).unwrap();
-                        crate::git_config::update_git_config(
-                            &profile.user_name,
-                            &profile.user_email,
-                        );
-                        *input_mode = InputMode::Normal;
-                    }
+                    let scope = Scope::resolve(switch_scope.clone(), config);
+                    profile::switch_profile(config, selected_profile, &scope)?;
+                    *input_mode = InputMode::Normal;
                 }
             }
             KeyCode::Char('b') | KeyCode::Esc => {
@@ -238,6 +288,29 @@ pub fn handle_input(
             }
             _ => {}
         },
+        InputMode::SwitchProfileFile => match key.code {
+            KeyCode::Char(c) => {
+                scope_file.push(c);
+            }
+            KeyCode::Backspace => {
+                scope_file.pop();
+            }
+            KeyCode::Enter if !scope_file.is_empty() => {
+                if let Some(name) = selected_profile_to_switch.clone() {
+                    let scope = Scope::File(scope_file.as_str().into());
+                    profile::switch_profile(config, &name, &scope)?;
+                }
+                selected_profile_to_switch.take();
+                scope_file.clear();
+                *input_mode = InputMode::Normal;
+            }
+            KeyCode::Esc => {
+                selected_profile_to_switch.take();
+                scope_file.clear();
+                *input_mode = InputMode::SwitchProfile;
+            }
+            _ => {}
+        },
         InputMode::UpdateProfile => match key.code {
             KeyCode::Up | KeyCode::Char('k') => {
                 let i = update_state.selected().unwrap_or(0);
@@ -265,8 +338,7 @@ pub fn handle_input(
         InputMode::ConfirmDeleteProfile => match key.code {
             KeyCode::Char('y') => {
                 if let Some(ref profile) = selected_profile_to_delete {
-                    config.profiles.remove(profile);
-                    save_config(config);
+                    profile::delete_profile(config, profile)?;
                 }
                 *input_mode = InputMode::Normal;
                 *selected_profile_to_delete = None;
@@ -280,3 +352,36 @@ pub fn handle_input(
     }
     Ok(())
 }
+
+/// Cycle the switch screen's scope: default, then each entry of `Scope::CYCLE`.
+fn next_scope(current: &Option<Scope>) -> Option<Scope> {
+    match current {
+        None => Some(Scope::CYCLE[0].clone()),
+        Some(scope) => {
+            let i = Scope::CYCLE.iter().position(|s| s == scope)?;
+            Scope::CYCLE.get(i + 1).cloned()
+        }
+    }
+}
+
+/// Parse a line typed on the extra config screen: `key=value` replaces the
+/// key, `key+=value` adds another value and `!key` removes it.
+fn parse_extra_input(input: &str) -> Result<ProfileUpdate> {
+    if let Some(key) = input.strip_prefix('!') {
+        return Ok(ProfileUpdate {
+            unset: vec![key.trim().to_string()],
+            ..Default::default()
+        });
+    }
+    let (key, value) = profile::parse_assignment(input).map_err(LitError::InvalidConfig)?;
+    Ok(match key.strip_suffix('+') {
+        Some(key) => ProfileUpdate {
+            add: vec![(key.to_string(), value)],
+            ..Default::default()
+        },
+        None => ProfileUpdate {
+            set: vec![(key, value)],
+            ..Default::default()
+        },
+    })
+}
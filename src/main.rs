@@ -1,16 +1,43 @@
 mod config;
+mod doctor;
+mod error;
 mod git_config;
+mod hooks;
+mod identity;
 mod input;
+mod managed;
+mod migrations;
+mod profile;
+mod repo;
+mod resolve;
+mod rules;
+mod signing;
+mod ssh;
 mod tui_interface;
 
-use clap::{Arg, Command};
-use config::{load_config, save_config, GitConfig};
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use config::{load_config, save_config, DefaultScope, GitConfig};
+use error::{LitError, Result};
+use git_config::Scope;
+use profile::ProfileUpdate;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
 
 fn main() {
     let matches = Command::new("lit")
         .version("1.0")
         .author("Pranjal Mandavkar")
         .about("Git helper tool")
+        .after_help(error::EXIT_CODES_HELP)
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Path to the config file (overrides LIT_CONFIG)")
+                .value_parser(clap::value_parser!(PathBuf))
+                .global(true),
+        )
         .subcommand(
             Command::new("add-profile")
                 .about("Add a new git configuration profile")
@@ -31,6 +58,21 @@ fn main() {
                         .help("Git user email")
                         .required(true)
                         .index(3),
+                )
+                .arg(set_arg())
+                .args(signing_args())
+                .arg(ssh_key_arg())
+                .arg(agent_arg())
+                .arg(ssh_host_arg())
+                .args(url_rewrite_args())
+                .args(repo_args())
+                .args(rule_args())
+                .arg(
+                    Arg::new("generate_ssh_key")
+                        .long("generate-ssh-key")
+                        .help("Create an ed25519 key for the profile with ssh-keygen")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("ssh_key"),
                 ),
         )
         .subcommand(
@@ -41,7 +83,9 @@ fn main() {
                         .help("Name of the profile to switch to")
                         .required(true)
                         .index(1),
-                ),
+                )
+                .args(scope_args())
+                .group(ArgGroup::new("scope").multiple(false)),
         )
         .subcommand(
             Command::new("update-profile")
@@ -52,17 +96,63 @@ fn main() {
                         .required(true)
                         .index(1),
                 )
+                .arg(Arg::new("user_name").help("New git user name").index(2))
+                .arg(Arg::new("user_email").help("New git user email").index(3))
+                .arg(set_arg())
+                .args(signing_args())
+                .arg(ssh_key_arg())
+                .arg(agent_arg())
+                .arg(ssh_host_arg())
+                .args(url_rewrite_args())
+                .args(repo_args())
+                .args(rule_args())
                 .arg(
-                    Arg::new("user_name")
-                        .help("New git user name")
-                        .required(true)
-                        .index(2),
+                    Arg::new("remove_remote_pattern")
+                        .long("remove-remote-pattern")
+                        .value_name("PATTERN")
+                        .help("Drop a remote URL pattern from the profile")
+                        .action(ArgAction::Append),
                 )
                 .arg(
-                    Arg::new("user_email")
-                        .help("New git user email")
-                        .required(true)
-                        .index(3),
+                    Arg::new("remove_directory")
+                        .long("remove-directory")
+                        .value_name("DIR")
+                        .help("Stop using the profile for repositories under a directory")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("remove_url_rewrite")
+                        .long("remove-url-rewrite")
+                        .value_name("FROM")
+                        .help("Drop the profile's URL rewrites for a prefix")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("remove_ssh_host")
+                        .long("remove-ssh-host")
+                        .value_name("ALIAS")
+                        .help("Drop an SSH host alias from the profile")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("no_ssh_key")
+                        .long("no-ssh-key")
+                        .help("Stop selecting an SSH key for the profile")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("ssh_key"),
+                )
+                .arg(
+                    Arg::new("no_signing")
+                        .long("no-signing")
+                        .help("Remove the profile's signing settings")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("unset")
+                        .long("unset")
+                        .value_name("KEY")
+                        .help("Remove an extra git config key from the profile")
+                        .action(ArgAction::Append),
                 ),
         )
         .subcommand(
@@ -75,69 +165,793 @@ fn main() {
                         .index(1),
                 ),
         )
+        .subcommand(
+            Command::new("config")
+                .about("Manage the lit config file")
+                .subcommand_required(true)
+                .subcommand(Command::new("check").about("Validate the config file"))
+                .subcommand(
+                    Command::new("default-scope")
+                        .about("Show or set the scope used when switching without a scope flag")
+                        .arg(
+                            Arg::new("scope")
+                                .help("'local' uses the repository config inside a repo")
+                                .value_parser(["global", "local"])
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    Command::new("migrate")
+                        .about("Upgrade the config file to the current schema version")
+                        .arg(
+                            Arg::new("dry_run")
+                                .long("dry-run")
+                                .help("Show what would change without writing")
+                                .action(ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about("Restore the config from a backup")
+                        .arg(
+                            Arg::new("list")
+                                .long("list")
+                                .help("List available backups")
+                                .action(ArgAction::SetTrue)
+                                .conflicts_with("backup"),
+                        )
+                        .arg(
+                            Arg::new("backup")
+                                .help("Backup number to restore (1 is the newest)")
+                                .value_parser(clap::value_parser!(usize))
+                                .default_value("1")
+                                .index(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("whoami")
+                .visible_alias("status")
+                .about("Show the git identity in effect here and where it comes from"),
+        )
+        .subcommand(
+            Command::new("check-identity").about(
+                "Fail when user.email here differs from the profile the pin file or rules pick",
+            ),
+        )
+        .subcommand(
+            Command::new("check-push")
+                .about("Fail when commits git is about to push are not by the profile the rules pick for the remote")
+                .arg(
+                    Arg::new("remote")
+                        .help("Remote name, as git passes it to pre-push")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("url")
+                        .help("Remote URL, as git passes it to pre-push")
+                        .required(true)
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            Command::new("hooks")
+                .about("Install git hooks that check identities")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("install")
+                        .about("Install a pre-commit hook running 'lit check-identity'")
+                        .arg(
+                            Arg::new("pre_push")
+                                .long("pre-push")
+                                .help("Install a pre-push hook running 'lit check-push' instead")
+                                .action(ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("global")
+                                .long("global")
+                                .help("Install for every repository through core.hooksPath")
+                                .action(ArgAction::SetTrue),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor").about("Check git, the config file and profile signing keys"),
+        )
+        .subcommand(
+            Command::new("ssh")
+                .about("Manage SSH configuration generated from profiles")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("sync")
+                        .about("Rewrite lit's managed host block in ~/.ssh/config")
+                        .arg(
+                            Arg::new("dry_run")
+                                .long("dry-run")
+                                .help("Show the diff without writing")
+                                .action(ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("file")
+                                .long("file")
+                                .value_name("PATH")
+                                .help("SSH config file to manage instead of ~/.ssh/config")
+                                .value_parser(clap::value_parser!(PathBuf)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("apply").about(
+                "Write the profile picked by the repository's pin file or the rules to its local config",
+            ),
+        )
+        .subcommand(
+            Command::new("clone")
+                .about("Clone a repository and configure it with a profile")
+                .arg(profile_arg())
+                .arg(
+                    Arg::new("url")
+                        .help("Repository to clone")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("dir")
+                        .help("Directory to clone into")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Create a repository configured with a profile")
+                .arg(profile_arg())
+                .arg(
+                    Arg::new("dir")
+                        .help("Directory to create the repository in")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .default_value(".")
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("remote")
+                        .long("remote")
+                        .value_name("URL")
+                        .help("Add URL as origin, through the profile's SSH alias for its host"),
+                ),
+        )
+        .subcommand(
+            Command::new("rules")
+                .about("Manage the git config includes that pick a profile automatically")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("apply")
+                        .about("Regenerate profile fragments and the managed includeIf block"),
+                )
+                .subcommand(Command::new("show").about("Print the generated files"))
+                .subcommand(
+                    Command::new("list").about("List every rule in the order they are tried"),
+                )
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a rule to the rules section")
+                        .arg(
+                            Arg::new("profile")
+                                .help("Profile the rule picks")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::new("priority")
+                                .long("priority")
+                                .value_name("N")
+                                .help("Rules with higher priorities are tried first (default 0)")
+                                .value_parser(clap::value_parser!(i32))
+                                .allow_negative_numbers(true),
+                        )
+                        .arg(
+                            Arg::new("path")
+                                .long("path")
+                                .value_name("GLOB")
+                                .help(
+                                    "Match repositories whose path matches GLOB, e.g. '~/work/**'",
+                                ),
+                        )
+                        .arg(
+                            Arg::new("remote")
+                                .long("remote")
+                                .value_name("REGEX")
+                                .help("Match repositories with a remote URL matching REGEX"),
+                        )
+                        .arg(
+                            Arg::new("branch")
+                                .long("branch")
+                                .value_name("GLOB")
+                                .help("Match when the checked-out branch matches GLOB"),
+                        )
+                        .arg(
+                            Arg::new("hostname")
+                                .long("hostname")
+                                .value_name("GLOB")
+                                .help("Match on machines whose hostname matches GLOB"),
+                        )
+                        .group(
+                            ArgGroup::new("matcher")
+                                .args(["path", "remote", "branch", "hostname"])
+                                .multiple(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("remove").about("Remove a rule").arg(
+                        Arg::new("index")
+                            .help("Position of the rule, as shown by 'lit rules list'")
+                            .required(true)
+                            .value_parser(clap::value_parser!(usize))
+                            .index(1),
+                    ),
+                )
+                .subcommand(
+                    Command::new("lint")
+                        .about("Report rules that overlap or can never match"),
+                )
+                .subcommand(
+                    Command::new("test")
+                        .about("Explain which rule picks the profile for a repository")
+                        .arg(
+                            Arg::new("path")
+                                .help("Repository to check")
+                                .value_parser(clap::value_parser!(PathBuf))
+                                .default_value(".")
+                                .index(1),
+                        ),
+                ),
+        )
         .subcommand(Command::new("show-tui").about("Show the terminal user interface"))
         .subcommand(Command::new("list-profile").about("List all profiles"))
         .get_matches();
 
-    let mut config = load_config();
+    if let Some(path) = matches.get_one::<PathBuf>("config") {
+        config::set_config_path(path.clone());
+    }
+    // Hooks run without a user to answer, and check-push's stdin is git's.
+    let from_hook = matches!(
+        matches.subcommand_name(),
+        Some("check-identity" | "check-push")
+    );
+    if !from_hook {
+        if let Err(e) = config::migrate_legacy_config() {
+            eprintln!("Error moving legacy config: {}", e);
+        }
+    }
+
+    if let Err(e) = run(&matches) {
+        // A reader that stops early, like `head`, is not an error.
+        if matches!(e, LitError::Io(ref e) if e.kind() == io::ErrorKind::BrokenPipe) {
+            return;
+        }
+        eprintln!("Error: {}", e);
+        if matches!(e, LitError::InvalidConfig(_))
+            && !matches!(
+                matches.subcommand_name(),
+                Some("config" | "doctor" | "rules")
+            )
+        {
+            eprintln!("Run 'lit config check' for details or 'lit config restore' to roll back.");
+        }
+        process::exit(e.exit_code());
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    if let Some(matches) = matches.subcommand_matches("config") {
+        return run_config_command(matches);
+    }
+
+    let mut config = load_config()?;
 
     if let Some(matches) = matches.subcommand_matches("add-profile") {
         let name = matches.get_one::<String>("name").unwrap();
         let user_name = matches.get_one::<String>("user_name").unwrap();
         let user_email = matches.get_one::<String>("user_email").unwrap();
 
-        let git_config = GitConfig {
+        let mut git_config = GitConfig {
             user_name: user_name.to_string(),
             user_email: user_email.to_string(),
+            ..Default::default()
         };
+        profile_update_from_matches(matches).apply_to(&mut git_config);
 
-        config.profiles.insert(name.to_string(), git_config);
-        save_config(&config);
+        let has_hosts = !git_config.ssh_hosts.is_empty();
+        profile::add_profile(&mut config, name, git_config)?;
         println!("Profile '{}' added.", name);
+        if matches.get_flag("generate_ssh_key") {
+            let public_key = profile::generate_ssh_key(&mut config, name)?;
+            println!(
+                "Add this public key to your hosting service:\n{}",
+                public_key
+            );
+            if has_hosts {
+                println!("Run 'lit ssh sync' to use it for the profile's SSH hosts.");
+            }
+        }
     } else if let Some(matches) = matches.subcommand_matches("switch-profile") {
         let name = matches.get_one::<String>("name").unwrap();
 
-        if config.profiles.contains_key(name) {
-            config.current_profile = name.to_string();
-            let profile = config.profiles.get(name).unwrap();
-            git_config::update_git_config(&profile.user_name, &profile.user_email);
-            save_config(&config);
-            println!("Switched to profile '{}'.", name);
-        } else {
-            println!("Profile '{}' does not exist.", name);
-        }
+        let scope = Scope::resolve(scope_from_matches(matches), &config);
+        profile::switch_profile(&mut config, name, &scope)?;
+        println!("Switched to profile '{}' ({}).", name, scope);
     } else if let Some(matches) = matches.subcommand_matches("update-profile") {
         let name = matches.get_one::<String>("name").unwrap();
-        let user_name = matches.get_one::<String>("user_name").unwrap();
-        let user_email = matches.get_one::<String>("user_email").unwrap();
 
-        if let Some(profile) = config.profiles.get_mut(name) {
-            profile.user_name = user_name.to_string();
-            profile.user_email = user_email.to_string();
-            save_config(&config);
-            println!("Profile '{}' updated.", name);
-        } else {
-            println!("Profile '{}' does not exist.", name);
-        }
+        profile::update_profile(&mut config, name, &profile_update_from_matches(matches))?;
+        println!("Profile '{}' updated.", name);
     } else if let Some(matches) = matches.subcommand_matches("delete-profile") {
         let name = matches.get_one::<String>("name").unwrap();
 
-        if config.profiles.remove(name).is_some() {
-            if config.current_profile == *name {
-                config.current_profile.clear();
+        profile::delete_profile(&mut config, name)?;
+        println!("Profile '{}' deleted.", name);
+    } else if matches.subcommand_matches("whoami").is_some() {
+        identity::whoami(&config)?;
+    } else if matches.subcommand_matches("check-identity").is_some() {
+        identity::check_identity(&config)?;
+    } else if let Some(matches) = matches.subcommand_matches("check-push") {
+        identity::check_push(
+            &config,
+            matches.get_one::<String>("remote").unwrap(),
+            matches.get_one::<String>("url").unwrap(),
+            io::stdin().lock(),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("hooks") {
+        if let Some(matches) = matches.subcommand_matches("install") {
+            let (hook, check) = if matches.get_flag("pre_push") {
+                ("pre-push", "check-push")
+            } else {
+                ("pre-commit", "check-identity")
+            };
+            for line in hooks::install(hook, check, matches.get_flag("global"))? {
+                println!("{}", line);
             }
-            save_config(&config);
-            println!("Profile '{}' deleted.", name);
-        } else {
-            println!("Profile '{}' does not exist.", name);
         }
-    } else if let Some(_) = matches.subcommand_matches("show-tui") {
-        if let Err(e) = tui_interface::run_tui() {
-            eprintln!("Error running TUI: {}", e);
+    } else if matches.subcommand_matches("doctor").is_some() {
+        doctor::doctor(&config)?;
+    } else if let Some(matches) = matches.subcommand_matches("ssh") {
+        if let Some(matches) = matches.subcommand_matches("sync") {
+            let path = matches
+                .get_one::<PathBuf>("file")
+                .cloned()
+                .unwrap_or_else(ssh::ssh_config_path);
+            let plan = ssh::plan_sync(&config, &path)?;
+            if plan.is_noop() {
+                println!("{} is up to date.", path.display());
+            } else if matches.get_flag("dry_run") {
+                print!("{}", plan.diff());
+            } else {
+                managed::write(&plan)?;
+                println!("Updated {}.", path.display());
+            }
+        }
+    } else if matches.subcommand_matches("apply").is_some() {
+        let resolution = profile::apply(&mut config)?;
+        println!(
+            "Applied profile '{}' to this repository ({}).",
+            resolution.profile,
+            resolution.reason()
+        );
+    } else if let Some(matches) = matches.subcommand_matches("clone") {
+        let url = matches.get_one::<String>("url").unwrap();
+        let dir = matches
+            .get_one::<PathBuf>("dir")
+            .cloned()
+            .unwrap_or_else(|| repo::default_clone_dir(url));
+
+        let name = repo::choose_profile(
+            &config,
+            matches.get_one::<String>("profile").map(|s| s.as_str()),
+            &resolve::RepoFacts::planned(&dir, Some(url))?,
+        )?;
+        repo::clone(&config, &name, url, &dir)?;
+        if let Ok(Some(pinned)) = resolve::RepoFacts::of(&dir)?
+            .pin
+            .map(|pin| pin.profile(&config))
+            .transpose()
+        {
+            if pinned != name {
+                eprintln!(
+                    "warning: the repository pins profile '{}'; run 'lit apply' in it to use that",
+                    pinned
+                );
+            }
+        }
+        println!("Cloned into '{}' with profile '{}'.", dir.display(), name);
+    } else if let Some(matches) = matches.subcommand_matches("init") {
+        let dir = matches.get_one::<PathBuf>("dir").unwrap();
+        let remote = matches.get_one::<String>("remote");
+
+        let name = repo::choose_profile(
+            &config,
+            matches.get_one::<String>("profile").map(|s| s.as_str()),
+            &resolve::RepoFacts::planned(dir, remote.map(|s| s.as_str()))?,
+        )?;
+        if let Some(url) = repo::init(&config, &name, dir, remote.map(|s| s.as_str()))? {
+            println!("Added remote 'origin' at {}.", url);
+        }
+        println!("Configured '{}' with profile '{}'.", dir.display(), name);
+    } else if let Some(matches) = matches.subcommand_matches("rules") {
+        if matches.subcommand_matches("apply").is_some() {
+            let changed = rules::apply(&config)?;
+            if changed.is_empty() {
+                println!("Rules are up to date.");
+            }
+            for path in changed {
+                println!("Updated {}.", path.display());
+            }
+        } else if let Some(matches) = matches.subcommand_matches("test") {
+            rules::test(
+                &config,
+                matches.get_one::<PathBuf>("path").unwrap(),
+                &mut io::stdout().lock(),
+            )?;
+        } else if matches.subcommand_matches("list").is_some() {
+            let mut out = io::stdout().lock();
+            for candidate in resolve::candidates(&config) {
+                writeln!(
+                    out,
+                    "{:>4}  {:<12}  {:<28}  {}",
+                    candidate.priority,
+                    candidate.profile,
+                    candidate.origin,
+                    candidate.describe()
+                )?;
+            }
+        } else if let Some(matches) = matches.subcommand_matches("add") {
+            let optional = |id: &str| matches.get_one::<String>(id).cloned();
+            let rule = config::MatchRule {
+                profile: matches.get_one::<String>("profile").unwrap().clone(),
+                priority: matches.get_one::<i32>("priority").copied().unwrap_or(0),
+                path: optional("path"),
+                remote: optional("remote"),
+                branch: optional("branch"),
+                hostname: optional("hostname"),
+            };
+            if let Some(ref remote) = rule.remote {
+                regex::Regex::new(remote)
+                    .map_err(|e| LitError::InvalidConfig(format!("--remote: {}", e)))?;
+            }
+            profile::add_rule(&mut config, rule)?;
+            println!("Added rules[{}].", config.rules.len() - 1);
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            let index = *matches.get_one::<usize>("index").unwrap();
+            let rule = profile::remove_rule(&mut config, index)?;
+            println!("Removed rules[{}] for profile '{}'.", index, rule.profile);
+        } else if matches.subcommand_matches("lint").is_some() {
+            let problems = resolve::lint(&config);
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            if !problems.is_empty() {
+                return Err(LitError::InvalidConfig(format!(
+                    "lint found {} problem(s)",
+                    problems.len()
+                )));
+            }
+            println!("No problems found.");
+        } else if matches.subcommand_matches("show").is_some() {
+            let mut out = io::stdout().lock();
+            for (path, contents) in rules::render(&config) {
+                writeln!(out, "# {}", path.display())?;
+                writeln!(out, "{}", contents)?;
+            }
+        }
+    } else if matches.subcommand_matches("show-tui").is_some() {
+        tui_interface::run_tui()?;
+    } else if matches.subcommand_matches("list-profile").is_some() {
+        config::list_profiles(&mut io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+fn run_config_command(matches: &ArgMatches) -> Result<()> {
+    if matches.subcommand_matches("check").is_some() {
+        let path = config::config_path();
+        let problems = config::check_config()?;
+        if problems.is_empty() {
+            println!("{}: OK", path.display());
+            return Ok(());
+        }
+        for problem in &problems {
+            eprintln!("{}: {}", path.display(), problem);
+        }
+        return Err(LitError::InvalidConfig(format!(
+            "{} problem(s) found in {}",
+            problems.len(),
+            path.display()
+        )));
+    } else if let Some(matches) = matches.subcommand_matches("default-scope") {
+        let mut config = load_config()?;
+        match matches.get_one::<String>("scope").map(String::as_str) {
+            Some(scope) => {
+                config.default_scope = if scope == "local" {
+                    DefaultScope::Local
+                } else {
+                    DefaultScope::Global
+                };
+                save_config(&config)?;
+                println!("Default scope set to {}.", scope);
+            }
+            None => match config.default_scope {
+                DefaultScope::Global => println!("global"),
+                DefaultScope::Local => println!("local"),
+            },
+        }
+    } else if let Some(matches) = matches.subcommand_matches("migrate") {
+        let dry_run = matches.get_flag("dry_run");
+        let (steps, migrated) = config::migrate_config(dry_run)?;
+        if steps.is_empty() {
+            println!("Config is already up to date.");
+            return Ok(());
         }
-    } else if let Some(_) = matches.subcommand_matches("list-profile") {
-        if let Err(e) = config::list_profiles() {
-            eprintln!("Error listing profiles: {}", e);
+        for step in &steps {
+            println!("{}", step);
         }
+        if dry_run {
+            println!("\nMigrated config (not written):\n{}", migrated);
+        } else {
+            println!("Config migrated; previous version kept as backup 1.");
+        }
+    } else if let Some(matches) = matches.subcommand_matches("restore") {
+        if matches.get_flag("list") {
+            let backups = config::list_backups();
+            let mut out = io::stdout().lock();
+            if backups.is_empty() {
+                writeln!(out, "No backups found.")?;
+            }
+            for (n, path, modified) in backups {
+                let age = modified
+                    .and_then(|m| m.elapsed().ok())
+                    .map(|d| format!("{}s ago", d.as_secs()))
+                    .unwrap_or_else(|| String::from("unknown age"));
+                writeln!(out, "{}: {} ({})", n, path.display(), age)?;
+            }
+        } else {
+            let n = *matches.get_one::<usize>("backup").unwrap();
+            config::restore_backup(n)?;
+            println!("Restored config from backup {}.", n);
+        }
+    }
+    Ok(())
+}
+
+/// The `--local`/`--global`/`--worktree`/`--system`/`--file` flags shared by
+/// commands that write git config.
+fn scope_args() -> Vec<Arg> {
+    let flag = |id: &'static str, help: &'static str| {
+        Arg::new(id)
+            .long(id)
+            .help(help)
+            .action(ArgAction::SetTrue)
+            .group("scope")
+    };
+    vec![
+        flag("local", "Write to the repository config"),
+        flag("global", "Write to the user's global config"),
+        flag("worktree", "Write to the worktree config"),
+        flag("system", "Write to the system config"),
+        Arg::new("file")
+            .long("file")
+            .value_name("PATH")
+            .help("Write to the given config file")
+            .value_parser(clap::value_parser!(PathBuf))
+            .group("scope"),
+    ]
+}
+
+fn scope_from_matches(matches: &ArgMatches) -> Option<Scope> {
+    if matches.get_flag("local") {
+        Some(Scope::Local)
+    } else if matches.get_flag("global") {
+        Some(Scope::Global)
+    } else if matches.get_flag("worktree") {
+        Some(Scope::Worktree)
+    } else if matches.get_flag("system") {
+        Some(Scope::System)
+    } else {
+        matches.get_one::<PathBuf>("file").cloned().map(Scope::File)
+    }
+}
+
+fn set_arg() -> Arg {
+    Arg::new("set")
+        .long("set")
+        .value_name("KEY=VALUE")
+        .help("Extra git config value for the profile; repeat a key for multiple values")
+        .value_parser(profile::parse_assignment)
+        .action(ArgAction::Append)
+}
+
+fn signing_args() -> Vec<Arg> {
+    vec![
+        Arg::new("signing_key")
+            .long("signing-key")
+            .value_name("KEY")
+            .help("Key used to sign commits and tags (user.signingkey)"),
+        Arg::new("gpg_format")
+            .long("gpg-format")
+            .value_name("FORMAT")
+            .help("Signature format (gpg.format)")
+            .value_parser(config::GPG_FORMATS.to_vec()),
+        Arg::new("sign_commits")
+            .long("sign-commits")
+            .value_name("BOOL")
+            .help("Sign every commit (commit.gpgsign)")
+            .value_parser(clap::value_parser!(bool))
+            .num_args(0..=1)
+            .default_missing_value("true"),
+        Arg::new("sign_tags")
+            .long("sign-tags")
+            .value_name("BOOL")
+            .help("Sign every annotated tag (tag.gpgsign)")
+            .value_parser(clap::value_parser!(bool))
+            .num_args(0..=1)
+            .default_missing_value("true"),
+    ]
+}
+
+fn profile_arg() -> Arg {
+    Arg::new("profile")
+        .short('p')
+        .long("profile")
+        .value_name("NAME")
+        .help("Profile for the repository (default: the pin file's, the rules' or the current one)")
+}
+
+fn ssh_key_arg() -> Arg {
+    Arg::new("ssh_key")
+        .long("ssh-key")
+        .value_name("PATH")
+        .help("Private key to push and fetch with (core.sshCommand)")
+}
+
+fn agent_arg() -> Arg {
+    Arg::new("agent")
+        .long("agent")
+        .value_name("BOOL")
+        .help("Load the SSH key into ssh-agent on switch, unloading the previous profile's")
+        .value_parser(clap::value_parser!(bool))
+        .num_args(0..=1)
+        .default_missing_value("true")
+}
+
+fn ssh_host_arg() -> Arg {
+    Arg::new("ssh_host")
+        .long("ssh-host")
+        .value_name("ALIAS=[USER@]HOSTNAME")
+        .help("SSH host alias managed in ~/.ssh/config, e.g. github.com-work=git@github.com")
+        .value_parser(profile::parse_ssh_host)
+        .action(ArgAction::Append)
+}
+
+fn rule_args() -> Vec<Arg> {
+    vec![
+        Arg::new("directory")
+            .long("directory")
+            .value_name("DIR")
+            .help("Use the profile for every repository under DIR (applied by 'lit rules apply')")
+            .action(ArgAction::Append),
+        Arg::new("remote_pattern")
+            .long("remote-pattern")
+            .value_name("PATTERN")
+            .help("Use the profile for repositories with a remote URL matching PATTERN, e.g. '*github.com:acme/*'")
+            .action(ArgAction::Append),
+    ]
+}
+
+fn repo_args() -> Vec<Arg> {
+    vec![
+        Arg::new("default_branch")
+            .long("default-branch")
+            .value_name("BRANCH")
+            .help("Initial branch of new repositories (init.defaultBranch); empty to clear"),
+        Arg::new("commit_template")
+            .long("commit-template")
+            .value_name("PATH")
+            .help("Commit message template (commit.template); empty to clear"),
+    ]
+}
+
+fn url_rewrite_args() -> Vec<Arg> {
+    vec![
+        Arg::new("url_rewrite")
+            .long("url-rewrite")
+            .value_name("FROM=TO")
+            .help("Rewrite remote URLs starting with FROM to TO while active (url.TO.insteadOf)")
+            .value_parser(profile::parse_url_rewrite)
+            .action(ArgAction::Append),
+        Arg::new("push_url_rewrite")
+            .long("push-url-rewrite")
+            .value_name("FROM=TO")
+            .help("Like --url-rewrite, for push URLs only (url.TO.pushInsteadOf)")
+            .value_parser(profile::parse_url_rewrite)
+            .action(ArgAction::Append),
+    ]
+}
+
+fn profile_update_from_matches(matches: &ArgMatches) -> ProfileUpdate {
+    // Not every command defines every flag, so use the non-panicking lookups.
+    let optional = |id: &str| matches.try_get_one::<String>(id).ok().flatten().cloned();
+    let optional_bool = |id: &str| matches.try_get_one::<bool>(id).ok().flatten().copied();
+    let flag = |id: &str| optional_bool(id) == Some(true);
+    let strings = |id: &str| -> Vec<String> {
+        matches
+            .try_get_many::<String>(id)
+            .ok()
+            .flatten()
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default()
+    };
+    let url_rewrites = |id: &str, push_only: bool| {
+        matches
+            .try_get_many::<config::UrlRewrite>(id)
+            .ok()
+            .flatten()
+            .into_iter()
+            .flatten()
+            .map(move |rule| config::UrlRewrite {
+                push_only,
+                ..rule.clone()
+            })
+    };
+    ProfileUpdate {
+        user_name: optional("user_name"),
+        user_email: optional("user_email"),
+        set: matches
+            .try_get_many::<(String, String)>("set")
+            .ok()
+            .flatten()
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default(),
+        unset: matches
+            .try_get_many::<String>("unset")
+            .ok()
+            .flatten()
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default(),
+        no_signing: flag("no_signing"),
+        signing_key: optional("signing_key"),
+        gpg_format: optional("gpg_format"),
+        sign_commits: optional_bool("sign_commits"),
+        sign_tags: optional_bool("sign_tags"),
+        ssh_key: optional("ssh_key"),
+        no_ssh_key: flag("no_ssh_key"),
+        agent: optional_bool("agent"),
+        default_branch: optional("default_branch"),
+        commit_template: optional("commit_template"),
+        add_ssh_hosts: matches
+            .try_get_many::<config::SshHost>("ssh_host")
+            .ok()
+            .flatten()
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default(),
+        remove_ssh_hosts: matches
+            .try_get_many::<String>("remove_ssh_host")
+            .ok()
+            .flatten()
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default(),
+        add_url_rewrites: url_rewrites("url_rewrite", false)
+            .chain(url_rewrites("push_url_rewrite", true))
+            .collect(),
+        add_directories: strings("directory"),
+        remove_directories: strings("remove_directory"),
+        add_remote_patterns: strings("remote_pattern"),
+        remove_remote_patterns: strings("remove_remote_pattern"),
+        remove_url_rewrites: matches
+            .try_get_many::<String>("remove_url_rewrite")
+            .ok()
+            .flatten()
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default(),
+        ..Default::default()
     }
 }
@@ -0,0 +1,299 @@
+use crate::error::{LitError, Result};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A pending rewrite of the block lit manages inside a user's file.
+pub struct SyncPlan {
+    pub path: PathBuf,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+    existing: String,
+    contents: String,
+}
+
+impl SyncPlan {
+    pub fn is_noop(&self) -> bool {
+        self.contents == self.existing
+    }
+
+    /// The change to the managed block as a line diff.
+    pub fn diff(&self) -> String {
+        let mut out = format!(
+            "--- {}\n+++ {} (lit)\n",
+            self.path.display(),
+            self.path.display()
+        );
+        for (tag, line) in line_diff(&self.removed, &self.added) {
+            out.push_str(&format!("{}{}\n", tag, line));
+        }
+        out
+    }
+}
+
+/// Longest-common-subsequence diff of two short line lists, tagging each
+/// line with ' ', '-' or '+'.
+fn line_diff<'a>(old: &'a [String], new: &'a [String]) -> Vec<(char, &'a str)> {
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            out.push((' ', old[i].as_str()));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push(('+', new[j].as_str()));
+            j += 1;
+        } else {
+            out.push(('-', old[i].as_str()));
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Work out how `path` changes when the block between the `begin` and `end`
+/// marker lines is replaced with `added` (markers included, or empty to drop
+/// the block). Lines outside the block are kept as they are; a missing block
+/// is appended.
+pub fn plan(path: &Path, begin: &str, end: &str, added: Vec<String>) -> Result<SyncPlan> {
+    plan_block(path, begin, end, added, false)
+}
+
+/// Like [`plan`], but the block always ends up at the end of the file, for
+/// files where what comes last wins.
+pub fn plan_last(path: &Path, begin: &str, end: &str, added: Vec<String>) -> Result<SyncPlan> {
+    plan_block(path, begin, end, added, true)
+}
+
+fn plan_block(
+    path: &Path,
+    begin: &str,
+    end: &str,
+    added: Vec<String>,
+    last: bool,
+) -> Result<SyncPlan> {
+    let existing = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    // Lines keep their endings so everything outside the block is written
+    // back byte for byte.
+    let lines: Vec<&str> = existing.split_inclusive('\n').collect();
+    let begin_at = lines.iter().position(|l| l.trim() == begin);
+    let end_at = lines.iter().position(|l| l.trim() == end);
+    let (mut before, removed, mut after) = match (begin_at, end_at) {
+        (Some(b), Some(e)) if b < e => {
+            (lines[..b].to_vec(), &lines[b..=e], lines[e + 1..].to_vec())
+        }
+        (None, None) => (lines.clone(), &lines[..0], Vec::new()),
+        _ => {
+            return Err(LitError::InvalidConfig(format!(
+                "{}: unbalanced lit managed block markers",
+                path.display()
+            )))
+        }
+    };
+    let was_last = after.is_empty();
+    let append = begin_at.is_none() || last;
+    if last {
+        before.append(&mut after);
+    }
+
+    let mut contents = before.concat();
+    if !added.is_empty() && !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    if append && !added.is_empty() && before.last().is_some_and(|l| !l.trim().is_empty()) {
+        contents.push('\n');
+    }
+    for line in &added {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    if added.is_empty() && was_last && before.last().is_some_and(|l| l.trim().is_empty()) {
+        contents.truncate(contents.len() - before[before.len() - 1].len());
+    }
+    contents.push_str(&after.concat());
+
+    Ok(SyncPlan {
+        path: path.to_path_buf(),
+        removed: removed
+            .iter()
+            .map(|l| l.trim_end_matches(['\n', '\r']).to_string())
+            .collect(),
+        added,
+        existing,
+        contents,
+    })
+}
+
+/// Write the planned file through a temp file and rename. The existing
+/// file's permissions are kept; new files are private to the user.
+pub fn write(plan: &SyncPlan) -> Result<()> {
+    if let Some(dir) = plan.path.parent() {
+        if !dir.as_os_str().is_empty() && !dir.exists() {
+            fs::create_dir_all(dir)?;
+            set_private(dir, 0o700)?;
+        }
+    }
+    let mut tmp_name = plan.path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".lit.tmp");
+    let tmp = plan.path.with_file_name(tmp_name);
+    fs::write(&tmp, &plan.contents)?;
+    match fs::metadata(&plan.path) {
+        Ok(meta) => fs::set_permissions(&tmp, meta.permissions())?,
+        Err(_) => set_private(&tmp, 0o600)?,
+    }
+    fs::rename(&tmp, &plan.path)?;
+    Ok(())
+}
+
+/// `name` with every byte unsafe in a file name percent-encoded, so
+/// different names never share a file.
+pub fn file_stem(name: &str) -> String {
+    let mut out = String::new();
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b"._-".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+#[cfg(unix)]
+pub fn set_private(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn set_private(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEGIN: &str = "# BEGIN test";
+    const END: &str = "# END test";
+
+    fn scratch(name: &str, contents: Option<&str>) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("lit-managed-{}-{}", std::process::id(), name));
+        match contents {
+            Some(contents) => fs::write(&path, contents).unwrap(),
+            None => {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        path
+    }
+
+    fn block(body: &str) -> Vec<String> {
+        vec![BEGIN.to_string(), body.to_string(), END.to_string()]
+    }
+
+    fn sync(path: &Path, added: Vec<String>) -> SyncPlan {
+        let plan = plan(path, BEGIN, END, added).unwrap();
+        write(&plan).unwrap();
+        plan
+    }
+
+    #[test]
+    fn a_missing_block_is_appended() {
+        let path = scratch("append", Some("Host *\n  User me"));
+        let plan = sync(&path, block("one"));
+        assert!(plan.removed.is_empty());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "Host *\n  User me\n\n# BEGIN test\none\n# END test\n"
+        );
+
+        let path = scratch("create", None);
+        sync(&path, block("one"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# BEGIN test\none\n# END test\n"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_present_block_is_replaced_in_place() {
+        let before = "a\r\n  b \n\n";
+        let after = "\tc\r\nd";
+        let path = scratch(
+            "replace",
+            Some(&format!("{}{}\nold\n{}\n{}", before, BEGIN, END, after)),
+        );
+        let plan = sync(&path, block("new"));
+        assert_eq!(plan.removed, block("old"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}{}\nnew\n{}\n{}", before, BEGIN, END, after)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_rerun_changes_nothing() {
+        let path = scratch("rerun", Some("keep\n"));
+        sync(&path, block("one"));
+        let first = fs::read_to_string(&path).unwrap();
+        let plan = sync(&path, block("one"));
+        assert!(plan.is_noop());
+        assert_eq!(fs::read_to_string(&path).unwrap(), first);
+
+        // Dropping the block gives the file back as it was.
+        sync(&path, Vec::new());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_block_kept_last_moves_to_the_end() {
+        let path = scratch("last", None);
+        sync(&path, block("one"));
+        let mut contents = fs::read_to_string(&path).unwrap();
+        contents.push_str("[user]\n\temail = a@x.org\n");
+        fs::write(&path, &contents).unwrap();
+
+        let plan = plan_last(&path, BEGIN, END, block("one")).unwrap();
+        assert!(!plan.is_noop());
+        write(&plan).unwrap();
+        let moved = "[user]\n\temail = a@x.org\n\n# BEGIN test\none\n# END test\n";
+        assert_eq!(fs::read_to_string(&path).unwrap(), moved);
+        assert!(plan_last(&path, BEGIN, END, block("one"))
+            .unwrap()
+            .is_noop());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn an_unterminated_block_is_an_error() {
+        let contents = format!("keep\n{}\nold\nmore\n", BEGIN);
+        let path = scratch("unterminated", Some(&contents));
+        match plan(&path, BEGIN, END, block("new")) {
+            Err(e) => assert!(e.to_string().contains("unbalanced"), "{}", e),
+            Ok(_) => panic!("an unterminated block was planned"),
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        fs::remove_file(&path).unwrap();
+    }
+}
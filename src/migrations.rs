@@ -0,0 +1,65 @@
+use serde_json::{Map, Value};
+
+/// Schema version written by this build of lit.
+pub const CONFIG_VERSION: u64 = 1;
+
+struct Migration {
+    /// Version the migration upgrades from.
+    from: u64,
+    description: &'static str,
+    apply: fn(&mut Map<String, Value>),
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "add 'version' and fill in missing 'profiles' and 'current_profile'",
+    apply: v0_to_v1,
+}];
+
+fn v0_to_v1(root: &mut Map<String, Value>) {
+    root.entry("profiles")
+        .or_insert_with(|| Value::Object(Map::new()));
+    root.entry("current_profile")
+        .or_insert_with(|| Value::String(String::new()));
+}
+
+/// Version of a raw config document. Files written before versioning count as 0.
+pub fn version_of(value: &Value) -> Result<u64, String> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| format!("'version' must be a non-negative integer, got {}", v)),
+    }
+}
+
+/// Upgrade `value` to `CONFIG_VERSION` in place and return a description of
+/// every step applied. An empty list means the document was already current.
+pub fn migrate(value: &mut Value) -> Result<Vec<String>, String> {
+    let mut version = version_of(value)?;
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "config version {} is newer than this lit supports ({})",
+            version, CONFIG_VERSION
+        ));
+    }
+    let Some(root) = value.as_object_mut() else {
+        return Err(String::from("top level must be an object"));
+    };
+
+    let mut applied = Vec::new();
+    while version < CONFIG_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or_else(|| format!("no migration from config version {}", version))?;
+        (migration.apply)(root);
+        version += 1;
+        root.insert(String::from("version"), Value::from(version));
+        applied.push(format!(
+            "v{} -> v{}: {}",
+            migration.from, version, migration.description
+        ));
+    }
+    Ok(applied)
+}
@@ -0,0 +1,458 @@
+use crate::config::{save_config, Config, GitConfig, MatchRule, SshHost, UrlRewrite};
+use crate::error::{LitError, Result};
+use crate::git_config::{self, Scope};
+use crate::resolve::{self, RepoFacts, Resolution};
+use crate::rules;
+use crate::signing;
+use crate::ssh;
+use std::path::Path;
+
+/// Apply `change` to a copy of `config` and persist it. `config` is only
+/// replaced once the save succeeded, so a failed write never leaves the
+/// in-memory state ahead of the file.
+fn commit(config: &mut Config, change: impl FnOnce(&mut Config) -> Result<()>) -> Result<()> {
+    let mut next = config.clone();
+    change(&mut next)?;
+    save_config(&next)?;
+    *config = next;
+    Ok(())
+}
+
+/// Regenerate files derived from the full set of profiles after one of them
+/// was added, changed or removed.
+fn profiles_changed(config: &Config) -> Result<()> {
+    signing::regenerate_allowed_signers(config)?;
+    rules::apply(config).map(|_| ())
+}
+
+pub fn add_profile(config: &mut Config, name: &str, profile: GitConfig) -> Result<()> {
+    commit(config, |config| {
+        if config.profiles.contains_key(name) {
+            return Err(LitError::ProfileExists(name.to_string()));
+        }
+        config.profiles.insert(name.to_string(), profile);
+        Ok(())
+    })?;
+    profiles_changed(config)
+}
+
+/// Write the profile's identity to `scope`. A global switch also records the
+/// profile as `current_profile`.
+pub fn switch_profile(config: &mut Config, name: &str, scope: &Scope) -> Result<()> {
+    let profile = config
+        .profiles
+        .get(name)
+        .ok_or_else(|| LitError::ProfileNotFound(name.to_string()))?;
+    git_config::update_git_config(scope, profile)?;
+    if *scope != Scope::Global {
+        switch_agent_keys(config, None, name);
+        return Ok(());
+    }
+    let previous = config.current_profile.clone();
+    commit(config, |config| {
+        config.current_profile = name.to_string();
+        Ok(())
+    })?;
+    switch_agent_keys(config, Some(&previous), name);
+    Ok(())
+}
+
+/// Write the profile the pin file, rules or current profile pick for the
+/// repository in the current directory to its local config.
+pub fn apply(config: &mut Config) -> Result<Resolution> {
+    let here = Path::new(".");
+    if git_config::show_toplevel(here)?.is_none() {
+        return Err(LitError::GitFailed(String::from(
+            "not inside a git repository",
+        )));
+    }
+    let resolution = resolve::resolve(config, &RepoFacts::of(here)?)?.ok_or_else(|| {
+        LitError::NoProfile(String::from(
+            "no pin file or rule matches and there is no current profile",
+        ))
+    })?;
+    switch_profile(config, &resolution.profile, &Scope::Local)?;
+    Ok(resolution)
+}
+
+/// Unload the key of the `previous` global profile from ssh-agent and load
+/// the key of `next`, for profiles that opt in with `agent`. The switch has
+/// already happened, so failures are only reported.
+fn switch_agent_keys(config: &Config, previous: Option<&str>, next: &str) {
+    let agent_key = |name: &str| {
+        config
+            .profiles
+            .get(name)
+            .filter(|p| p.agent)
+            .and_then(|p| p.ssh_key.clone())
+    };
+    let next_key = agent_key(next);
+    if let Some(key) = previous.and_then(agent_key) {
+        if next_key.as_ref() != Some(&key) {
+            if let Err(e) = ssh::agent_remove(&key) {
+                eprintln!("warning: could not remove {} from ssh-agent: {}", key, e);
+            }
+        }
+    }
+    if let Some(key) = next_key {
+        if let Err(e) = ssh::agent_add(&key) {
+            eprintln!("warning: could not add {} to ssh-agent: {}", key, e);
+        }
+    }
+}
+
+/// Changes to an existing profile. `None` and empty lists leave that part of
+/// the profile as it is.
+#[derive(Default)]
+pub struct ProfileUpdate {
+    pub user_name: Option<String>,
+    pub user_email: Option<String>,
+    /// Extra git config values. All values given for one key replace that
+    /// key's previous values.
+    pub set: Vec<(String, String)>,
+    /// Extra git config values appended after any existing ones.
+    pub add: Vec<(String, String)>,
+    /// Extra git config keys to drop.
+    pub unset: Vec<String>,
+    /// Clear every signing setting before applying the ones below.
+    pub no_signing: bool,
+    pub signing_key: Option<String>,
+    pub gpg_format: Option<String>,
+    pub sign_commits: Option<bool>,
+    pub sign_tags: Option<bool>,
+    pub ssh_key: Option<String>,
+    pub no_ssh_key: bool,
+    pub agent: Option<bool>,
+    pub default_branch: Option<String>,
+    pub commit_template: Option<String>,
+    /// Host aliases to add, replacing any with the same alias.
+    pub add_ssh_hosts: Vec<SshHost>,
+    /// Host aliases to drop.
+    pub remove_ssh_hosts: Vec<String>,
+    /// URL rewrites to add, replacing any with the same `from` and kind.
+    pub add_url_rewrites: Vec<UrlRewrite>,
+    /// `from` prefixes whose rewrites are dropped.
+    pub remove_url_rewrites: Vec<String>,
+    pub add_directories: Vec<String>,
+    pub remove_directories: Vec<String>,
+    pub add_remote_patterns: Vec<String>,
+    pub remove_remote_patterns: Vec<String>,
+}
+
+impl ProfileUpdate {
+    pub fn apply_to(&self, profile: &mut GitConfig) {
+        if let Some(ref user_name) = self.user_name {
+            profile.user_name = user_name.clone();
+        }
+        if let Some(ref user_email) = self.user_email {
+            profile.user_email = user_email.clone();
+        }
+        if self.no_signing {
+            profile.signing_key = None;
+            profile.gpg_format = None;
+            profile.sign_commits = false;
+            profile.sign_tags = false;
+        }
+        if let Some(ref key) = self.signing_key {
+            profile.signing_key = Some(key.clone());
+        }
+        if let Some(ref format) = self.gpg_format {
+            profile.gpg_format = Some(format.clone());
+        }
+        if let Some(sign) = self.sign_commits {
+            profile.sign_commits = sign;
+        }
+        if let Some(sign) = self.sign_tags {
+            profile.sign_tags = sign;
+        }
+        if self.no_ssh_key {
+            profile.ssh_key = None;
+            profile.agent = false;
+        }
+        if let Some(ref key) = self.ssh_key {
+            profile.ssh_key = Some(key.clone());
+        }
+        if let Some(agent) = self.agent {
+            profile.agent = agent;
+        }
+        if let Some(ref branch) = self.default_branch {
+            profile.default_branch = Some(branch.clone()).filter(|b| !b.is_empty());
+        }
+        if let Some(ref template) = self.commit_template {
+            profile.commit_template = Some(template.clone()).filter(|t| !t.is_empty());
+        }
+        profile
+            .ssh_hosts
+            .retain(|h| !self.remove_ssh_hosts.contains(&h.alias));
+        for host in &self.add_ssh_hosts {
+            profile.ssh_hosts.retain(|h| h.alias != host.alias);
+            profile.ssh_hosts.push(host.clone());
+        }
+        profile
+            .url_rewrites
+            .retain(|r| !self.remove_url_rewrites.contains(&r.from));
+        for rule in &self.add_url_rewrites {
+            profile
+                .url_rewrites
+                .retain(|r| r.from != rule.from || r.push_only != rule.push_only);
+            profile.url_rewrites.push(rule.clone());
+        }
+        profile
+            .directories
+            .retain(|d| !self.remove_directories.contains(d));
+        for dir in &self.add_directories {
+            if !profile.directories.contains(dir) {
+                profile.directories.push(dir.clone());
+            }
+        }
+        profile
+            .remote_patterns
+            .retain(|p| !self.remove_remote_patterns.contains(p));
+        for pattern in &self.add_remote_patterns {
+            if !profile.remote_patterns.contains(pattern) {
+                profile.remote_patterns.push(pattern.clone());
+            }
+        }
+        for key in &self.unset {
+            profile.extra_config.remove(&git_config::normalize_key(key));
+        }
+        let mut replaced = Vec::new();
+        for (key, value) in &self.set {
+            let key = git_config::normalize_key(key);
+            let values = profile.extra_config.entry(key.clone()).or_default();
+            if !replaced.contains(&key) {
+                values.clear();
+                replaced.push(key);
+            }
+            values.push(value.clone());
+        }
+        for (key, value) in &self.add {
+            let key = git_config::normalize_key(key);
+            profile
+                .extra_config
+                .entry(key)
+                .or_default()
+                .push(value.clone());
+        }
+    }
+}
+
+/// Parse an `ALIAS=[USER@]HOSTNAME` host alias as taken by `--ssh-host`.
+pub fn parse_ssh_host(s: &str) -> std::result::Result<SshHost, String> {
+    let (alias, target) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected ALIAS=[USER@]HOSTNAME, got '{}'", s))?;
+    let (user, hostname) = match target.split_once('@') {
+        Some((user, hostname)) => (Some(user.to_string()), hostname),
+        None => (None, target),
+    };
+    let word = |w: &str| !w.is_empty() && !w.contains(char::is_whitespace);
+    if !word(alias) || !word(hostname) || user.as_deref().is_some_and(|u| !word(u)) {
+        return Err(format!("expected ALIAS=[USER@]HOSTNAME, got '{}'", s));
+    }
+    Ok(SshHost {
+        alias: alias.to_string(),
+        hostname: hostname.to_string(),
+        user,
+    })
+}
+
+/// Parse a `FROM=TO` URL rewrite as taken by `--url-rewrite`.
+pub fn parse_url_rewrite(s: &str) -> std::result::Result<UrlRewrite, String> {
+    match s.split_once('=') {
+        Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => Ok(UrlRewrite {
+            from: from.trim().to_string(),
+            to: to.trim().to_string(),
+            push_only: false,
+        }),
+        _ => Err(format!("expected FROM=TO, got '{}'", s)),
+    }
+}
+
+/// Parse a `key=value` assignment as taken by `--set` and the TUI.
+pub fn parse_assignment(s: &str) -> std::result::Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got '{}'", s))?;
+    let key = key.trim();
+    if !key.contains('.') || key.starts_with('.') || key.ends_with('.') {
+        return Err(format!("'{}' is not a section.key name", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+pub fn update_profile(config: &mut Config, name: &str, update: &ProfileUpdate) -> Result<()> {
+    commit(config, |config| {
+        let profile = config
+            .profiles
+            .get_mut(name)
+            .ok_or_else(|| LitError::ProfileNotFound(name.to_string()))?;
+        update.apply_to(profile);
+        Ok(())
+    })?;
+    profiles_changed(config)
+}
+
+pub fn delete_profile(config: &mut Config, name: &str) -> Result<()> {
+    commit(config, |config| {
+        if config.profiles.remove(name).is_none() {
+            return Err(LitError::ProfileNotFound(name.to_string()));
+        }
+        if config.current_profile == name {
+            config.current_profile.clear();
+        }
+        config.rules.retain(|rule| rule.profile != name);
+        Ok(())
+    })?;
+    profiles_changed(config)
+}
+
+/// Generate an SSH key for an existing profile and record it as the
+/// profile's `ssh_key`. Returns the public key for the user to register with
+/// their hosting service.
+pub fn generate_ssh_key(config: &mut Config, name: &str) -> Result<String> {
+    let email = config
+        .profiles
+        .get(name)
+        .ok_or_else(|| LitError::ProfileNotFound(name.to_string()))?
+        .user_email
+        .clone();
+    let (path, public_key) = ssh::generate_key(name, &email)?;
+    let update = ProfileUpdate {
+        ssh_key: Some(path),
+        ..Default::default()
+    };
+    update_profile(config, name, &update)?;
+    Ok(public_key)
+}
+
+/// Append `rule` to the `rules` section.
+pub fn add_rule(config: &mut Config, rule: MatchRule) -> Result<()> {
+    commit(config, |config| {
+        if !config.profiles.contains_key(&rule.profile) {
+            return Err(LitError::ProfileNotFound(rule.profile.clone()));
+        }
+        config.rules.push(rule);
+        Ok(())
+    })
+}
+
+/// Remove the rule at `index` in the `rules` section.
+pub fn remove_rule(config: &mut Config, index: usize) -> Result<MatchRule> {
+    let mut removed = None;
+    commit(config, |config| {
+        if index >= config.rules.len() {
+            return Err(LitError::InvalidConfig(format!(
+                "there is no rules[{}]; the config has {} rule(s)",
+                index,
+                config.rules.len()
+            )));
+        }
+        removed = Some(config.rules.remove(index));
+        Ok(())
+    })?;
+    Ok(removed.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DefaultScope;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::process::{Child, Command, Stdio};
+    use std::{env, fs, thread, time::Duration};
+
+    /// An ssh-agent listening on a socket in `dir`, killed on drop.
+    struct Agent(Child);
+
+    impl Agent {
+        fn spawn(dir: &Path) -> Option<Agent> {
+            let sock = dir.join("agent.sock");
+            let child = Command::new("ssh-agent")
+                .arg("-D")
+                .arg("-a")
+                .arg(&sock)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let agent = Agent(child);
+            for _ in 0..50 {
+                if sock.exists() {
+                    env::set_var("SSH_AUTH_SOCK", &sock);
+                    return Some(agent);
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            None
+        }
+    }
+
+    impl Drop for Agent {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn keygen(path: &Path) -> Option<String> {
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+            .arg(path)
+            .status()
+            .ok()?;
+        let public = fs::read_to_string(path.with_extension("pub")).ok()?;
+        status
+            .success()
+            .then(|| public.split_whitespace().nth(1).unwrap().to_string())
+    }
+
+    fn loaded_keys() -> String {
+        let output = Command::new("ssh-add").arg("-L").output().unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    #[test]
+    fn switch_agent_keys_swaps_the_loaded_key() {
+        let dir = env::temp_dir().join(format!("lit-agent-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let Some(_agent) = Agent::spawn(&dir) else {
+            eprintln!("skipping: ssh-agent is not available");
+            return;
+        };
+        let (Some(work_blob), Some(home_blob)) =
+            (keygen(&dir.join("work")), keygen(&dir.join("home")))
+        else {
+            eprintln!("skipping: ssh-keygen is not available");
+            return;
+        };
+
+        let profile = |key: &str| GitConfig {
+            agent: true,
+            ssh_key: Some(dir.join(key).display().to_string()),
+            ..Default::default()
+        };
+        let config = Config {
+            version: 0,
+            profiles: HashMap::from([
+                (String::from("work"), profile("work")),
+                (String::from("home"), profile("home")),
+            ]),
+            current_profile: String::new(),
+            default_scope: DefaultScope::default(),
+            rules: Vec::new(),
+        };
+
+        switch_agent_keys(&config, None, "work");
+        let keys = loaded_keys();
+        assert!(keys.contains(&work_blob) && !keys.contains(&home_blob));
+
+        switch_agent_keys(&config, Some("work"), "home");
+        let keys = loaded_keys();
+        assert!(keys.contains(&home_blob) && !keys.contains(&work_blob));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::config::{Config, GitConfig};
+use crate::error::{LitError, Result};
+use crate::git_config::{self, Scope};
+use crate::resolve::{self, RepoFacts};
+use crate::ssh;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The profile a new repository gets: the one named with `-p`, otherwise
+/// the one the rules pick for it.
+pub fn choose_profile(
+    config: &Config,
+    explicit: Option<&str>,
+    facts: &RepoFacts,
+) -> Result<String> {
+    let name = match explicit {
+        Some(name) => name.to_string(),
+        None => match resolve::resolve(config, facts)? {
+            Some(resolution) => resolution.profile,
+            None => {
+                return Err(LitError::NoProfile(String::from(
+                    "no rule matches; pass --profile or switch to a profile first",
+                )))
+            }
+        },
+    };
+    if !config.profiles.contains_key(&name) {
+        return Err(LitError::ProfileNotFound(name));
+    }
+    Ok(name)
+}
+
+/// `-c` options giving a one-off git command the profile's SSH key and URL
+/// rewrites, for commands that run before the repository config exists.
+fn transport_args(profile: &GitConfig) -> Vec<String> {
+    let mut args = Vec::new();
+    for (key, values) in git_config::profile_entries(profile) {
+        if key != "core.sshcommand" && !key.starts_with("url.") {
+            continue;
+        }
+        for value in values {
+            args.push(String::from("-c"));
+            args.push(format!("{}={}", key, value));
+        }
+    }
+    args
+}
+
+/// The directory `git clone` picks for `url` when none is given: the last
+/// path component without a trailing `.git`.
+pub fn default_clone_dir(url: &str) -> PathBuf {
+    let trimmed = url.trim_end_matches('/');
+    let trimmed = trimmed.strip_suffix("/.git").unwrap_or(trimmed);
+    let last = trimmed.rsplit(['/', ':']).next().unwrap_or(trimmed);
+    PathBuf::from(last.strip_suffix(".git").unwrap_or(last))
+}
+
+/// The repository's local config, where a local switch writes. Git finds
+/// it, so a separate git directory works too.
+fn local_config(dir: &Path) -> Scope {
+    Scope::LocalAt(dir.to_path_buf())
+}
+
+fn run_git(args: &[String]) -> Result<()> {
+    let status = Command::new("git")
+        .args(args)
+        .status()
+        .map_err(|e| LitError::GitFailed(format!("could not run git: {}", e)))?;
+    if !status.success() {
+        return Err(LitError::GitFailed(format!(
+            "`git {}` exited with {}",
+            args.join(" "),
+            status
+        )));
+    }
+    Ok(())
+}
+
+/// Clone `url` with the profile's SSH key and URL rewrites, then write the
+/// profile to the new repository's local config.
+pub fn clone(config: &Config, name: &str, url: &str, dir: &Path) -> Result<()> {
+    let profile = config
+        .profiles
+        .get(name)
+        .ok_or_else(|| LitError::ProfileNotFound(name.to_string()))?;
+
+    let mut args = transport_args(profile);
+    args.push(String::from("clone"));
+    args.push(String::from("--"));
+    args.push(url.to_string());
+    args.push(dir.display().to_string());
+    run_git(&args)?;
+
+    git_config::update_git_config(&local_config(dir), profile)
+}
+
+/// `git init` `dir` on the profile's default branch and write the profile to
+/// its local config. With `remote`, add it as `origin`, going through the
+/// profile's SSH host alias when it has one for the remote's host. Returns
+/// the remote URL added.
+pub fn init(
+    config: &Config,
+    name: &str,
+    dir: &Path,
+    remote: Option<&str>,
+) -> Result<Option<String>> {
+    let profile = config
+        .profiles
+        .get(name)
+        .ok_or_else(|| LitError::ProfileNotFound(name.to_string()))?;
+
+    let mut args = vec![String::from("init")];
+    let branch = git_config::profile_entries(profile)
+        .into_iter()
+        .find(|(key, _)| key == "init.defaultbranch")
+        .and_then(|(_, values)| values.last().cloned());
+    if let Some(branch) = branch {
+        args.push(format!("--initial-branch={}", branch));
+    }
+    args.push(String::from("--"));
+    args.push(dir.display().to_string());
+    run_git(&args)?;
+
+    git_config::update_git_config(&local_config(dir), profile)?;
+
+    let Some(remote) = remote else {
+        return Ok(None);
+    };
+    let url = ssh::alias_url(profile, remote);
+    run_git(&[
+        String::from("-C"),
+        dir.display().to_string(),
+        String::from("remote"),
+        String::from("add"),
+        String::from("origin"),
+        url.clone(),
+    ])?;
+    Ok(Some(url))
+}
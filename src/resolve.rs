@@ -0,0 +1,664 @@
+use crate::config::{self, Config};
+use crate::error::{LitError, Result};
+use crate::git_config;
+use crate::rules::wildmatch;
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// File at a repository's root naming the profile, or the email, the
+/// repository must be committed with.
+pub const PIN_FILE: &str = ".lit-profile";
+
+/// The contents of a repository's pin file.
+#[derive(Clone)]
+pub struct Pin {
+    pub path: PathBuf,
+    /// A profile name, or an email address when it contains `@`.
+    pub value: String,
+}
+
+impl Pin {
+    /// Read the pin file in `root`: its first line that is neither blank
+    /// nor a `#` comment. `None` when there is no such file or line.
+    pub fn read(root: &Path) -> Result<Option<Pin>> {
+        let path = root.join(PIN_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|value| Pin {
+                path,
+                value: value.to_string(),
+            }))
+    }
+
+    pub fn is_email(&self) -> bool {
+        self.value.contains('@')
+    }
+
+    /// The pinned profile: the one named, or the first by name with the
+    /// pinned email.
+    pub fn profile(&self, config: &Config) -> Result<String> {
+        if config.profiles.contains_key(&self.value) {
+            return Ok(self.value.clone());
+        }
+        let mut names: Vec<&String> = config.profiles.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .find(|name| {
+                self.is_email()
+                    && config.profiles[*name]
+                        .user_email
+                        .eq_ignore_ascii_case(&self.value)
+            })
+            .cloned()
+            .ok_or_else(|| {
+                LitError::NoProfile(format!(
+                    "{} pins '{}', which matches no profile",
+                    self.path.display(),
+                    self.value
+                ))
+            })
+    }
+
+    /// The email commits must use here, if it can be worked out.
+    pub fn expected_email(&self, config: &Config) -> Option<String> {
+        if self.is_email() {
+            return Some(self.value.clone());
+        }
+        config
+            .profiles
+            .get(&self.value)
+            .map(|p| p.user_email.clone())
+    }
+}
+
+/// What the resolver looks at when picking a profile for a repository.
+pub struct RepoFacts {
+    /// The work tree root, or the directory itself outside a repository.
+    pub path: PathBuf,
+    pub remote_urls: Vec<String>,
+    pub branch: Option<String>,
+    pub hostname: String,
+    pub pin: Option<Pin>,
+}
+
+impl RepoFacts {
+    /// Facts about the repository containing `dir`, or about `dir` alone
+    /// when it is not inside one.
+    pub fn of(dir: &Path) -> Result<RepoFacts> {
+        let (path, pin) = match git_config::show_toplevel(dir)? {
+            Some(top) => {
+                let pin = Pin::read(&top)?;
+                (top, pin)
+            }
+            None => (absolute(dir)?, None),
+        };
+        Ok(RepoFacts {
+            pin,
+            remote_urls: git_config::remote_urls(dir)?
+                .into_iter()
+                .map(|(_, url)| url)
+                .collect(),
+            branch: git_config::current_branch(dir)?,
+            hostname: hostname(),
+            path,
+        })
+    }
+
+    /// Facts about a repository about to be created at `dir` with `remote`,
+    /// for `lit clone` and `lit init`.
+    pub fn planned(dir: &Path, remote: Option<&str>) -> Result<RepoFacts> {
+        Ok(RepoFacts {
+            path: absolute(dir)?,
+            remote_urls: remote.into_iter().map(String::from).collect(),
+            branch: None,
+            hostname: hostname(),
+            pin: None,
+        })
+    }
+}
+
+fn absolute(dir: &Path) -> Result<PathBuf> {
+    Ok(std::env::current_dir()?.join(dir))
+}
+
+/// This machine's hostname, or an empty string when it cannot be read.
+fn hostname() -> String {
+    if let Ok(name) = fs::read_to_string("/proc/sys/kernel/hostname") {
+        return name.trim().to_string();
+    }
+    Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+/// How a candidate matches remote URLs: a regex from the `rules` section or
+/// a git wildmatch pattern from a profile's `remote_patterns`.
+#[derive(Clone)]
+pub enum RemoteMatcher {
+    Regex(String),
+    Glob(String),
+}
+
+/// One way of picking a profile, with the matchers it requires. Unset
+/// matchers match anything.
+#[derive(Clone)]
+pub struct Candidate {
+    pub profile: String,
+    pub priority: i32,
+    /// Where the candidate was declared, e.g. `rules[0]`.
+    pub origin: String,
+    pub path: Option<String>,
+    pub remote: Option<RemoteMatcher>,
+    pub branch: Option<String>,
+    pub hostname: Option<String>,
+}
+
+/// Expand `~/` and let a trailing `/` stand for the whole tree below it.
+fn path_glob(pattern: &str) -> String {
+    let mut glob = config::expand_tilde(pattern).display().to_string();
+    if glob.ends_with('/') {
+        glob.push_str("**");
+    }
+    glob
+}
+
+impl Candidate {
+    /// The matchers as `field=pattern` pairs.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(ref path) = self.path {
+            parts.push(format!("path={}", path));
+        }
+        match self.remote {
+            Some(RemoteMatcher::Regex(ref re)) => parts.push(format!("remote=/{}/", re)),
+            Some(RemoteMatcher::Glob(ref glob)) => parts.push(format!("remote={}", glob)),
+            None => {}
+        }
+        if let Some(ref branch) = self.branch {
+            parts.push(format!("branch={}", branch));
+        }
+        if let Some(ref hostname) = self.hostname {
+            parts.push(format!("hostname={}", hostname));
+        }
+        parts.join(" ")
+    }
+
+    pub fn matches(&self, facts: &RepoFacts) -> Result<bool> {
+        if let Some(ref pattern) = self.path {
+            let glob = path_glob(pattern);
+            let path = facts.path.display().to_string();
+            let with_slash = format!("{}/", path);
+            if !wildmatch(glob.as_bytes(), path.as_bytes())
+                && !wildmatch(glob.as_bytes(), with_slash.as_bytes())
+            {
+                return Ok(false);
+            }
+        }
+        let remote_matches = match self.remote {
+            Some(RemoteMatcher::Regex(ref re)) => {
+                let re = Regex::new(re).map_err(|e| {
+                    LitError::InvalidConfig(format!("{}.remote: {}", self.origin, e))
+                })?;
+                facts.remote_urls.iter().any(|url| re.is_match(url))
+            }
+            Some(RemoteMatcher::Glob(ref glob)) => facts
+                .remote_urls
+                .iter()
+                .any(|url| wildmatch(glob.as_bytes(), url.as_bytes())),
+            None => true,
+        };
+        if !remote_matches {
+            return Ok(false);
+        }
+        if let Some(ref pattern) = self.branch {
+            let matched = facts
+                .branch
+                .as_ref()
+                .is_some_and(|b| wildmatch(pattern.as_bytes(), b.as_bytes()));
+            if !matched {
+                return Ok(false);
+            }
+        }
+        if let Some(ref pattern) = self.hostname {
+            if !wildmatch(pattern.as_bytes(), facts.hostname.as_bytes()) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Every candidate in the order the resolver tries them: by priority, then
+/// the `rules` section in list order, then the rules profiles declare with
+/// `directories` and `remote_patterns` (priority 0), latest include first
+/// as git applies them.
+pub fn candidates(config: &Config) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = config
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| Candidate {
+            profile: rule.profile.clone(),
+            priority: rule.priority,
+            origin: format!("rules[{}]", i),
+            path: rule.path.clone(),
+            remote: rule.remote.clone().map(RemoteMatcher::Regex),
+            branch: rule.branch.clone(),
+            hostname: rule.hostname.clone(),
+        })
+        .collect();
+
+    let mut names: Vec<&String> = config.profiles.keys().collect();
+    names.sort();
+    let mut declared = Vec::new();
+    for name in names {
+        let profile = &config.profiles[name];
+        for dir in &profile.directories {
+            let path = if dir.ends_with('/') {
+                dir.clone()
+            } else {
+                format!("{}/", dir)
+            };
+            declared.push(Candidate {
+                profile: name.clone(),
+                priority: 0,
+                origin: format!("profiles.{}.directories", name),
+                path: Some(path),
+                remote: None,
+                branch: None,
+                hostname: None,
+            });
+        }
+        for pattern in &profile.remote_patterns {
+            declared.push(Candidate {
+                profile: name.clone(),
+                priority: 0,
+                origin: format!("profiles.{}.remote_patterns", name),
+                path: None,
+                remote: Some(RemoteMatcher::Glob(pattern.clone())),
+                branch: None,
+                hostname: None,
+            });
+        }
+    }
+    declared.reverse();
+    candidates.extend(declared);
+
+    // A stable sort keeps list order within a priority.
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.priority));
+    candidates
+}
+
+/// Why a profile was picked.
+pub enum Source {
+    Pin(Pin),
+    Rule(Candidate),
+    /// No rule matched and the current profile was used.
+    Current,
+}
+
+/// The profile picked for a repository and why.
+pub struct Resolution {
+    pub profile: String,
+    pub source: Source,
+}
+
+impl Resolution {
+    pub fn reason(&self) -> String {
+        match self.source {
+            Source::Pin(ref pin) => format!("pinned by {}", pin.path.display()),
+            Source::Rule(ref c) => format!("{} matches ({})", c.origin, c.describe()),
+            Source::Current => String::from("no rule matches; using the current profile"),
+        }
+    }
+}
+
+/// Pick the profile for the repository described by `facts`: its pin file
+/// wins, then the first matching candidate, then the current profile.
+/// `None` when none of them applies; an error when the pin names no profile.
+pub fn resolve(config: &Config, facts: &RepoFacts) -> Result<Option<Resolution>> {
+    if let Some(ref pin) = facts.pin {
+        return Ok(Some(Resolution {
+            profile: pin.profile(config)?,
+            source: Source::Pin(pin.clone()),
+        }));
+    }
+    for candidate in candidates(config) {
+        if candidate.matches(facts)? {
+            return Ok(Some(Resolution {
+                profile: candidate.profile.clone(),
+                source: Source::Rule(candidate),
+            }));
+        }
+    }
+    if config.current_profile.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Resolution {
+        profile: config.current_profile.clone(),
+        source: Source::Current,
+    }))
+}
+
+/// The text of `pattern` before its first wildcard, and whether it has one.
+/// A regex character followed by a quantifier may be absent, so it is left
+/// out too.
+fn literal_prefix(pattern: &str, regex: bool) -> (&str, bool) {
+    let specials: &[char] = if regex {
+        &[
+            '.', '*', '+', '?', '(', ')', '[', ']', '{', '}', '|', '\\', '$',
+        ]
+    } else {
+        &['*', '?', '[', '\\']
+    };
+    let pattern = if regex {
+        pattern.strip_prefix('^').unwrap_or(pattern)
+    } else {
+        pattern
+    };
+    match pattern.find(specials) {
+        Some(i) if regex && pattern[i..].starts_with(['?', '*', '+', '{']) => {
+            let quantified = pattern[..i].char_indices().last().map_or(0, |(j, _)| j);
+            (&pattern[..quantified], true)
+        }
+        Some(i) => (&pattern[..i], true),
+        None => (pattern, false),
+    }
+}
+
+/// Whether no value can match both patterns, judged from their literal
+/// prefixes. Unanchored regexes and alternations are never considered
+/// disjoint.
+fn disjoint(a: &str, b: &str, regex: bool) -> bool {
+    let anchored = |re: &str| re.starts_with('^') && !re.contains('|');
+    if regex && !(anchored(a) && anchored(b)) {
+        return false;
+    }
+    let (a_prefix, a_wild) = literal_prefix(a, regex);
+    let (b_prefix, b_wild) = literal_prefix(b, regex);
+    match (a_wild, b_wild) {
+        (false, false) => a_prefix != b_prefix,
+        (true, false) => !b_prefix.starts_with(a_prefix),
+        (false, true) => !a_prefix.starts_with(b_prefix),
+        (true, true) => !a_prefix.starts_with(b_prefix) && !b_prefix.starts_with(a_prefix),
+    }
+}
+
+/// Whether the pattern matches every value. A glob `*` stops at `/`, so
+/// only `**` counts.
+fn catch_all(pattern: &str, regex: bool) -> bool {
+    if regex {
+        matches!(pattern, "" | ".*" | "^.*" | "^.*$")
+    } else {
+        pattern == "**"
+    }
+}
+
+/// Each matcher of a candidate as `(field, pattern, is_regex)`.
+fn matchers(c: &Candidate) -> Vec<(&'static str, String, bool)> {
+    let mut out = Vec::new();
+    if let Some(ref path) = c.path {
+        out.push(("path", path_glob(path), false));
+    }
+    match c.remote {
+        Some(RemoteMatcher::Regex(ref re)) => out.push(("remote", re.clone(), true)),
+        // Globs and regexes cannot be compared, so give them distinct fields.
+        Some(RemoteMatcher::Glob(ref glob)) => out.push(("remote glob", glob.clone(), false)),
+        None => {}
+    }
+    if let Some(ref branch) = c.branch {
+        out.push(("branch", branch.clone(), false));
+    }
+    if let Some(ref hostname) = c.hostname {
+        out.push(("hostname", hostname.clone(), false));
+    }
+    out
+}
+
+/// Whether `earlier` matches everything `later` matches: each of its
+/// matchers is a catch-all or appears unchanged in `later`.
+fn covers(earlier: &Candidate, later: &Candidate) -> bool {
+    let later_matchers = matchers(later);
+    matchers(earlier).iter().all(|(field, pattern, regex)| {
+        catch_all(pattern, *regex)
+            || later_matchers
+                .iter()
+                .any(|(f, p, _)| f == field && p == pattern)
+    })
+}
+
+/// Whether some repository could match both candidates.
+fn may_overlap(a: &Candidate, b: &Candidate) -> bool {
+    let b_matchers = matchers(b);
+    !matchers(a).iter().any(|(field, pattern, regex)| {
+        b_matchers
+            .iter()
+            .any(|(f, p, _)| f == field && disjoint(pattern, p, *regex))
+    })
+}
+
+/// Problems with the candidates: invalid regexes, rules an earlier rule
+/// always shadows, and rules for different profiles at the same priority
+/// that may both match, leaving the choice to list order.
+pub fn lint(config: &Config) -> Vec<String> {
+    let candidates = candidates(config);
+    let mut problems = Vec::new();
+    for c in &candidates {
+        if let Some(RemoteMatcher::Regex(ref re)) = c.remote {
+            if let Err(e) = Regex::new(re) {
+                problems.push(format!("{}: invalid remote regex: {}", c.origin, e));
+            }
+        }
+        if !config.profiles.contains_key(&c.profile) {
+            problems.push(format!("{}: unknown profile '{}'", c.origin, c.profile));
+        }
+    }
+    for (j, later) in candidates.iter().enumerate() {
+        let earlier = &candidates[..j];
+        if let Some(shadow) = earlier.iter().find(|e| covers(e, later)) {
+            problems.push(format!(
+                "{} ({}) is unreachable: {} ({}) always matches first",
+                later.origin,
+                later.describe(),
+                shadow.origin,
+                shadow.describe()
+            ));
+            continue;
+        }
+        for e in earlier {
+            if e.priority == later.priority && e.profile != later.profile && may_overlap(e, later) {
+                problems.push(format!(
+                    "{} ({}, profile '{}') and {} ({}, profile '{}') may both match at priority {}; the earlier one wins",
+                    e.origin,
+                    e.describe(),
+                    e.profile,
+                    later.origin,
+                    later.describe(),
+                    later.profile,
+                    later.priority
+                ));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DefaultScope, GitConfig, MatchRule};
+    use std::collections::HashMap;
+
+    fn config(rules: Vec<MatchRule>, work_patterns: &[&str]) -> Config {
+        let work = GitConfig {
+            remote_patterns: work_patterns.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+        Config {
+            version: 0,
+            profiles: HashMap::from([
+                (String::from("work"), work),
+                (String::from("home"), GitConfig::default()),
+            ]),
+            current_profile: String::new(),
+            default_scope: DefaultScope::default(),
+            rules,
+        }
+    }
+
+    fn rule(profile: &str, priority: i32) -> MatchRule {
+        MatchRule {
+            profile: profile.to_string(),
+            priority,
+            ..Default::default()
+        }
+    }
+
+    fn remote(profile: &str, priority: i32, re: &str) -> MatchRule {
+        MatchRule {
+            remote: Some(re.to_string()),
+            ..rule(profile, priority)
+        }
+    }
+
+    fn path(profile: &str, priority: i32, glob: &str) -> MatchRule {
+        MatchRule {
+            path: Some(glob.to_string()),
+            ..rule(profile, priority)
+        }
+    }
+
+    #[test]
+    fn lint_reports_rules_shadowed_by_a_catch_all() {
+        let cases = [
+            (
+                vec![path("home", 10, "**"), path("work", 0, "~/work/")],
+                Some("rules[1]"),
+            ),
+            (
+                vec![remote("home", 10, ".*"), remote("work", 0, "acme")],
+                Some("rules[1]"),
+            ),
+            (
+                vec![remote("home", 0, "acme"), remote("work", 0, "acme")],
+                Some("rules[1]"),
+            ),
+            // Higher priorities are tried first, whatever the list order.
+            (
+                vec![path("work", 0, "~/work/"), path("home", 10, "**")],
+                Some("rules[0]"),
+            ),
+            // `*` stops at `/`, so it does not match every path.
+            (
+                vec![path("home", 10, "*"), path("work", 0, "~/work/")],
+                None,
+            ),
+            (
+                vec![path("home", 10, "~/oss/"), path("work", 0, "~/work/")],
+                None,
+            ),
+        ];
+        for (rules, expected) in cases {
+            let problems = lint(&config(rules, &[]));
+            let unreachable: Vec<&str> = problems
+                .iter()
+                .filter(|p| p.contains("is unreachable"))
+                .filter_map(|p| p.split(' ').next())
+                .collect();
+            assert_eq!(unreachable, Vec::from_iter(expected), "{:?}", problems);
+        }
+    }
+
+    #[test]
+    fn lint_reports_overlaps_between_profiles_at_one_priority() {
+        let cases = [
+            (
+                vec![remote("work", 0, "github\\.com"), remote("home", 0, "acme")],
+                true,
+            ),
+            (
+                vec![remote("work", 0, "github"), remote("home", 5, "acme")],
+                false,
+            ),
+            (
+                vec![remote("work", 0, "github"), remote("work", 0, "acme")],
+                false,
+            ),
+            (
+                vec![path("work", 0, "~/work/"), path("home", 0, "~/oss/")],
+                false,
+            ),
+            (
+                vec![path("work", 0, "~/src/*"), path("home", 0, "~/src/oss/")],
+                true,
+            ),
+            (
+                vec![
+                    remote("work", 0, "^https://|^git@"),
+                    remote("home", 0, "^git@gitlab"),
+                ],
+                true,
+            ),
+        ];
+        for (rules, overlap) in cases {
+            let problems = lint(&config(rules, &[]));
+            assert_eq!(
+                problems.iter().any(|p| p.contains("may both match")),
+                overlap,
+                "{:?}",
+                problems
+            );
+        }
+    }
+
+    #[test]
+    fn anchored_regexes_with_different_prefixes_are_disjoint() {
+        let cases = [
+            ("^git@github\\.com:", "^git@gitlab\\.com:", true),
+            ("^git@github\\.com:acme/", "^git@github\\.com:", false),
+            ("^https://", "^git@", true),
+            ("^https://", "git@", false),
+            ("github", "gitlab", false),
+            // The character before a quantifier may be missing.
+            ("^https?://a", "^http://a", false),
+            ("^ab*c", "^ac", false),
+            ("^a{0,1}b", "^b", false),
+            ("^https://|^git@", "^git@gitlab", false),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(disjoint(a, b, true), expected, "{} vs {}", a, b);
+            assert_eq!(disjoint(b, a, true), expected, "{} vs {}", b, a);
+        }
+    }
+
+    #[test]
+    fn remote_globs_and_regexes_are_not_compared() {
+        // The same text as a glob and as a regex neither shadows nor is
+        // disjoint from the other.
+        let rules = vec![remote("home", 10, "^git@github\\.com:acme/")];
+        let problems = lint(&config(rules, &["git@github.com:acme/*"]));
+        assert!(problems.is_empty(), "{:?}", problems);
+
+        let rules = vec![remote("home", 0, "^https://")];
+        let problems = lint(&config(rules, &["git@github.com:acme/*"]));
+        assert!(
+            problems.iter().any(|p| p.contains("may both match")),
+            "{:?}",
+            problems
+        );
+    }
+}
@@ -0,0 +1,649 @@
+use crate::config::{self, Config, GitConfig};
+use crate::error::{LitError, Result};
+use crate::git_config;
+use crate::managed;
+use crate::resolve::{self, RepoFacts};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const BLOCK_BEGIN: &str =
+    "# BEGIN lit managed includes (generated from lit profiles, edits will be lost)";
+const BLOCK_END: &str = "# END lit managed includes";
+
+/// The global git config file, as `git config --global` writes it:
+/// `~/.gitconfig`, unless only the XDG one exists.
+pub fn global_config_path() -> PathBuf {
+    if let Some(path) = env::var_os("GIT_CONFIG_GLOBAL") {
+        return PathBuf::from(path);
+    }
+    let home = config::expand_tilde("~/.gitconfig");
+    let xdg = match env::var_os("XDG_CONFIG_HOME").filter(|p| !p.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("git").join("config"),
+        None => config::expand_tilde("~/.config/git/config"),
+    };
+    if !home.exists() && xdg.exists() {
+        return xdg;
+    }
+    home
+}
+
+/// Directory holding one generated git config fragment per profile, next to
+/// the config file.
+pub fn fragment_dir() -> PathBuf {
+    config::config_path().with_file_name("gitconfig")
+}
+
+fn fragment_path(name: &str) -> PathBuf {
+    fragment_dir().join(format!("{}.gitconfig", managed::file_stem(name)))
+}
+
+/// Quote a value for a git config file when git would otherwise read it
+/// differently.
+fn quote_value(value: &str) -> String {
+    let plain = !value.is_empty()
+        && !value.starts_with(char::is_whitespace)
+        && !value.ends_with(char::is_whitespace)
+        && !value.contains(['#', ';', '"', '\\', '\n', '\t']);
+    if plain {
+        return value.to_string();
+    }
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn section_header(section: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(sub) => format!(
+            "[{} \"{}\"]",
+            section,
+            sub.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        None => format!("[{}]", section),
+    }
+}
+
+/// The git config fragment that applies `profile` when included.
+fn render_fragment(name: &str, profile: &GitConfig) -> String {
+    let mut out = format!("# Generated by lit from profile '{}'. Do not edit.\n", name);
+    let mut current: Option<String> = None;
+    for (key, values) in git_config::profile_entries(profile) {
+        if values.is_empty() {
+            continue;
+        }
+        let (Some(first), Some(last)) = (key.find('.'), key.rfind('.')) else {
+            continue;
+        };
+        let subsection = (first < last).then(|| &key[first + 1..last]);
+        let header = section_header(&key[..first], subsection);
+        if current.as_ref() != Some(&header) {
+            out.push_str(&header);
+            out.push('\n');
+            current = Some(header);
+        }
+        for value in values {
+            out.push_str(&format!(
+                "\t{} = {}\n",
+                &key[last + 1..],
+                quote_value(&value)
+            ));
+        }
+    }
+    out
+}
+
+/// What makes git include a profile's fragment.
+pub enum Condition {
+    /// The repository's git directory is under this directory root.
+    GitDir(String),
+    /// One of the repository's remote URLs matches this pattern.
+    RemoteUrl(String),
+}
+
+impl Condition {
+    /// The condition as written in `[includeIf "..."]`.
+    pub fn include_condition(&self) -> String {
+        match self {
+            Condition::GitDir(dir) if dir.ends_with('/') => format!("gitdir:{}", dir),
+            Condition::GitDir(dir) => format!("gitdir:{}/", dir),
+            Condition::RemoteUrl(pattern) => format!("hasconfig:remote.*.url:{}", pattern),
+        }
+    }
+
+    /// Whether git would include the fragment for a repository with
+    /// `git_dir` and `remote_urls`, following git's matching rules.
+    fn matches(&self, git_dir: &Path, remote_urls: &[(String, String)]) -> bool {
+        match self {
+            Condition::GitDir(dir) => {
+                let mut pattern = config::expand_tilde(dir).display().to_string();
+                if !pattern.starts_with('/') {
+                    pattern.insert_str(0, "**/");
+                }
+                if !pattern.ends_with('/') {
+                    pattern.push('/');
+                }
+                pattern.push_str("**");
+                let git_dir = git_dir.display().to_string();
+                wildmatch(pattern.as_bytes(), git_dir.as_bytes())
+            }
+            Condition::RemoteUrl(pattern) => remote_urls
+                .iter()
+                .any(|(_, url)| wildmatch(pattern.as_bytes(), url.as_bytes())),
+        }
+    }
+}
+
+/// A profile's condition, in the order the includes are written.
+pub struct Rule {
+    pub profile: String,
+    pub condition: Condition,
+}
+
+/// Every rule of every profile: profiles by name, directories before remote
+/// patterns. git reads the includes in this order, so later matches win.
+pub fn rules(config: &Config) -> Vec<Rule> {
+    let mut names: Vec<&String> = config.profiles.keys().collect();
+    names.sort();
+
+    let mut rules = Vec::new();
+    for name in names {
+        let profile = &config.profiles[name];
+        let dirs = profile.directories.iter().cloned().map(Condition::GitDir);
+        let remotes = profile
+            .remote_patterns
+            .iter()
+            .cloned()
+            .map(Condition::RemoteUrl);
+        rules.extend(dirs.chain(remotes).map(|condition| Rule {
+            profile: name.clone(),
+            condition,
+        }));
+    }
+    rules
+}
+
+fn has_rules(profile: &GitConfig) -> bool {
+    !profile.directories.is_empty() || !profile.remote_patterns.is_empty()
+}
+
+/// `hasconfig:remote.*.url:` conditions need git 2.36 or later.
+fn supports_hasconfig() -> bool {
+    git_config::version().is_ok_and(|v| v >= (2, 36))
+}
+
+/// git's wildmatch for `includeIf` patterns: `*`, `?` and bracket
+/// expressions stop at `/`, `**` crosses it only as a whole path component
+/// (at the start or after a `/`, and at the end or before one) and `\`
+/// escapes the next character.
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    wildmatch_at(pattern, 0, text)
+}
+
+/// Match `pattern[at..]` against `text`; the whole pattern is kept to see
+/// what precedes a `**`.
+fn wildmatch_at(pattern: &[u8], at: usize, text: &[u8]) -> bool {
+    match &pattern[at..] {
+        [] => text.is_empty(),
+        [b'*', ..] => {
+            let stars = pattern[at..].iter().take_while(|&&c| c == b'*').count();
+            let rest = at + stars;
+            let whole_component = (at == 0 || pattern[at - 1] == b'/')
+                && matches!(&pattern[rest..], [] | [b'/', ..] | [b'\\', b'/', ..]);
+            if stars > 1 && whole_component {
+                // `**/` also matches no directory at all.
+                if pattern.get(rest) == Some(&b'/') && wildmatch_at(pattern, rest + 1, text) {
+                    return true;
+                }
+                return (0..=text.len()).any(|i| wildmatch_at(pattern, rest, &text[i..]));
+            }
+            let limit = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=limit).any(|i| wildmatch_at(pattern, rest, &text[i..]))
+        }
+        [b'?', ..] => match text {
+            [c, text @ ..] if *c != b'/' => wildmatch_at(pattern, at + 1, text),
+            _ => false,
+        },
+        [b'[', class @ ..] => match (text, bracket(class)) {
+            ([c, text @ ..], Some((set, len))) if *c != b'/' && set.contains(*c) => {
+                wildmatch_at(pattern, at + 1 + len, text)
+            }
+            _ => false,
+        },
+        [b'\\', c, ..] | [c, ..] => {
+            let len = if pattern[at] == b'\\' && pattern.len() > at + 1 {
+                2
+            } else {
+                1
+            };
+            match text {
+                [t, text @ ..] if t == c => wildmatch_at(pattern, at + len, text),
+                _ => false,
+            }
+        }
+    }
+}
+
+/// A parsed bracket expression: its items and whether it is negated.
+struct Bracket {
+    negated: bool,
+    ranges: Vec<(u8, u8)>,
+    classes: Vec<fn(&u8) -> bool>,
+}
+
+impl Bracket {
+    fn contains(&self, c: u8) -> bool {
+        let found = self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi)
+            || self.classes.iter().any(|class| class(&c));
+        found != self.negated
+    }
+}
+
+/// Parse the bracket expression after a `[`, returning it and the length up
+/// to and including its `]`. `None` when it is unterminated or names an
+/// unknown class, which git never matches.
+fn bracket(class: &[u8]) -> Option<(Bracket, usize)> {
+    let mut set = Bracket {
+        negated: matches!(class.first(), Some(b'!' | b'^')),
+        ranges: Vec::new(),
+        classes: Vec::new(),
+    };
+    let mut i = usize::from(set.negated);
+    let start = i;
+    loop {
+        let mut lo = *class.get(i)?;
+        if lo == b']' && i > start {
+            return Some((set, i + 1));
+        }
+        if lo == b'[' && class.get(i + 1) == Some(&b':') {
+            let close = i + 2 + class[i + 2..].iter().position(|&c| c == b']')?;
+            if class[close - 1] == b':' && close > i + 3 {
+                set.classes.push(posix_class(&class[i + 2..close - 1])?);
+                i = close + 1;
+                continue;
+            }
+        }
+        if lo == b'\\' {
+            i += 1;
+            lo = *class.get(i)?;
+        }
+        let mut hi = lo;
+        if class.get(i + 1) == Some(&b'-') && class.get(i + 2).is_some_and(|&c| c != b']') {
+            i += 2;
+            hi = *class.get(i)?;
+            if hi == b'\\' {
+                i += 1;
+                hi = *class.get(i)?;
+            }
+        }
+        set.ranges.push((lo, hi));
+        i += 1;
+    }
+}
+
+fn posix_class(name: &[u8]) -> Option<fn(&u8) -> bool> {
+    Some(match name {
+        b"alnum" => u8::is_ascii_alphanumeric,
+        b"alpha" => u8::is_ascii_alphabetic,
+        b"blank" => |c: &u8| *c == b' ' || *c == b'\t',
+        b"cntrl" => u8::is_ascii_control,
+        b"digit" => u8::is_ascii_digit,
+        b"graph" => u8::is_ascii_graphic,
+        b"lower" => u8::is_ascii_lowercase,
+        b"print" => |c: &u8| c.is_ascii_graphic() || *c == b' ',
+        b"punct" => u8::is_ascii_punctuation,
+        b"space" => u8::is_ascii_whitespace,
+        b"upper" => u8::is_ascii_uppercase,
+        b"xdigit" => u8::is_ascii_hexdigit,
+        _ => return None,
+    })
+}
+
+/// Lines of the managed includeIf block, markers included. Empty when no
+/// profile has rules.
+fn render_include_block(config: &Config) -> Vec<String> {
+    let hasconfig = supports_hasconfig();
+    let mut lines = Vec::new();
+    for rule in rules(config) {
+        if matches!(rule.condition, Condition::RemoteUrl(_)) && !hasconfig {
+            continue;
+        }
+        let path = fragment_path(&rule.profile).display().to_string();
+        lines.push(section_header(
+            "includeIf",
+            Some(&rule.condition.include_condition()),
+        ));
+        lines.push(format!("\tpath = {}", quote_value(&path)));
+    }
+    if lines.is_empty() {
+        return lines;
+    }
+    lines.insert(0, String::from(BLOCK_BEGIN));
+    lines.push(String::from(BLOCK_END));
+    lines
+}
+
+/// Every file `lit rules apply` writes, with its generated contents. For the
+/// global git config that is only the managed block.
+pub fn render(config: &Config) -> Vec<(PathBuf, String)> {
+    let mut names: Vec<&String> = config.profiles.keys().collect();
+    names.sort();
+
+    let mut files: Vec<(PathBuf, String)> = names
+        .into_iter()
+        .filter(|name| has_rules(&config.profiles[*name]))
+        .map(|name| {
+            (
+                fragment_path(name),
+                render_fragment(name, &config.profiles[name]),
+            )
+        })
+        .collect();
+    let block = render_include_block(config);
+    if !block.is_empty() {
+        files.push((global_config_path(), block.join("\n") + "\n"));
+    }
+    files
+}
+
+/// Regenerate the per-profile fragments and the managed includeIf block in
+/// the global git config, removing fragments no longer included. Returns
+/// the files that changed.
+pub fn apply(config: &Config) -> Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    let mut wanted = Vec::new();
+    for (name, profile) in &config.profiles {
+        if !has_rules(profile) {
+            continue;
+        }
+        let path = fragment_path(name);
+        let contents = render_fragment(name, profile);
+        if fs::read_to_string(&path).ok().as_deref() != Some(contents.as_str()) {
+            fs::create_dir_all(fragment_dir())?;
+            fs::write(&path, &contents)?;
+            changed.push(path.clone());
+        }
+        wanted.push(path);
+    }
+
+    match fs::read_dir(fragment_dir()) {
+        Ok(entries) => {
+            for entry in entries {
+                let path = entry?.path();
+                let generated = path.extension().is_some_and(|e| e == "gitconfig");
+                if generated && !wanted.contains(&path) {
+                    fs::remove_file(&path)?;
+                    changed.push(path);
+                }
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    if !supports_hasconfig()
+        && config
+            .profiles
+            .values()
+            .any(|p| !p.remote_patterns.is_empty())
+    {
+        eprintln!("warning: remote patterns need git 2.36 or later and were left out");
+    }
+    let plan = managed::plan_last(
+        &global_config_path(),
+        BLOCK_BEGIN,
+        BLOCK_END,
+        render_include_block(config),
+    )?;
+    if !plan.is_noop() {
+        managed::write(&plan)?;
+        changed.push(plan.path);
+    }
+    Ok(changed)
+}
+
+/// Move the managed include block back to the end of the global config, if
+/// it is there, so a section git appended after it does not override the
+/// profiles it includes.
+pub fn keep_includes_last() -> Result<()> {
+    let path = global_config_path();
+    let block = managed::plan(&path, BLOCK_BEGIN, BLOCK_END, Vec::new())?.removed;
+    if block.is_empty() {
+        return Ok(());
+    }
+    let plan = managed::plan_last(&path, BLOCK_BEGIN, BLOCK_END, block)?;
+    if !plan.is_noop() {
+        managed::write(&plan)?;
+    }
+    Ok(())
+}
+
+/// Print which rules match the repository at `path`, which one git applies
+/// and the identity git ends up with there.
+pub fn test(config: &Config, path: &Path, out: &mut impl Write) -> Result<()> {
+    let Some(git_dir) = git_config::git_dir(path)? else {
+        return Err(LitError::GitFailed(format!(
+            "{} is not inside a git repository",
+            path.display()
+        )));
+    };
+    let remote_urls = git_config::remote_urls(path)?;
+    writeln!(out, "repository  {}", git_dir.display())?;
+    for (name, url) in &remote_urls {
+        writeln!(out, "remote      {} {}", name, url)?;
+    }
+
+    let hasconfig = supports_hasconfig();
+    let mut winner = None;
+    writeln!(out)?;
+    for rule in rules(config) {
+        let condition = rule.condition.include_condition();
+        let unsupported = matches!(rule.condition, Condition::RemoteUrl(_)) && !hasconfig;
+        let status = if unsupported {
+            "skipped"
+        } else if rule.condition.matches(&git_dir, &remote_urls) {
+            winner = Some((rule.profile.clone(), condition.clone()));
+            "match"
+        } else {
+            "no match"
+        };
+        writeln!(out, "{:<10}  {:<12}  {}", status, rule.profile, condition)?;
+    }
+    writeln!(out)?;
+    match winner {
+        Some((profile, condition)) => writeln!(
+            out,
+            "Rule '{}' wins: it is the last matching include, so its profile '{}' overrides earlier ones.",
+            condition, profile
+        )?,
+        None => writeln!(out, "No rule matches; the global identity applies.")?,
+    }
+    match resolve::resolve(config, &RepoFacts::of(path)?)? {
+        Some(resolution) => writeln!(
+            out,
+            "lit resolves profile '{}': {}.",
+            resolution.profile,
+            resolution.reason()
+        )?,
+        None => writeln!(
+            out,
+            "lit resolves no profile: no rule matches and there is no current profile."
+        )?,
+    }
+
+    match git_config::effective_at(path, "user.email")? {
+        Some(email) => {
+            writeln!(
+                out,
+                "git resolves user.email = {} ({} {})",
+                email.value, email.scope, email.origin
+            )?;
+            if email.scope == "local" || email.scope == "worktree" {
+                writeln!(
+                    out,
+                    "The repository's own {} config overrides every rule.",
+                    email.scope
+                )?;
+            }
+        }
+        None => writeln!(out, "git resolves no user.email here.")?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches_dir(dir: &str, git_dir: &Path) -> bool {
+        Condition::GitDir(dir.to_string()).matches(git_dir, &[])
+    }
+
+    fn matches_url(pattern: &str, url: &str) -> bool {
+        let remotes = [(String::from("origin"), url.to_string())];
+        Condition::RemoteUrl(pattern.to_string()).matches(Path::new("/r/.git"), &remotes)
+    }
+
+    // Expected results were checked against `git config` with the same
+    // includeIf conditions.
+    #[test]
+    fn gitdir_conditions_match_like_git() {
+        let cases = [
+            ("~/work/", "~/work/a/.git", true),
+            ("~/work/", "~/workshop/a/.git", false),
+            // Written as `gitdir:~/work/`, as lit always adds the slash.
+            ("~/work", "~/work/a/.git", true),
+            ("/home/u/work/", "/home/u/work/a/.git", true),
+            ("/home/u/work/", "/home/v/work/a/.git", false),
+            ("x/", "/home/u/x/a/.git", true),
+            ("**/x/", "/x/a/.git", true),
+            ("**/x/", "/a/b/x/c/.git", true),
+            ("**/x/", "/a/xy/.git", false),
+            ("/home/*/work/", "/home/u/work/a/.git", true),
+            ("/home/*/work/", "/home/u/v/work/a/.git", false),
+        ];
+        for (dir, git_dir, expected) in cases {
+            let git_dir = config::expand_tilde(git_dir);
+            assert_eq!(
+                matches_dir(dir, &git_dir),
+                expected,
+                "gitdir:{} vs {}",
+                dir,
+                git_dir.display()
+            );
+        }
+    }
+
+    #[test]
+    fn remote_url_conditions_match_like_git() {
+        let cases = [
+            ("*github.com:acme/*", "git@github.com:acme/repo.git", true),
+            (
+                "*github.com:acme/*",
+                "https://github.com/acme/repo.git",
+                false,
+            ),
+            (
+                "*github.com:acme/*",
+                "git@github.com:acme/sub/repo.git",
+                false,
+            ),
+            (
+                "https://github.com/acme/**",
+                "https://github.com/acme/sub/repo.git",
+                true,
+            ),
+            (
+                "https://github.com/acme/*",
+                "https://github.com/acme/repo.git",
+                true,
+            ),
+            (
+                "*github.com?acme/*",
+                "https://github.com/acme/repo.git",
+                false,
+            ),
+            (
+                "**github.com?acme/*",
+                "https://github.com/acme/repo.git",
+                false,
+            ),
+            ("**/acme/*", "https://github.com/acme/repo.git", true),
+            // `**` not standing for whole path components acts like `*`.
+            (
+                "git@github.com:acme**",
+                "git@github.com:acme/sub/x.git",
+                false,
+            ),
+            ("git@github.com:[a]cme/*", "git@github.com:acme/x.git", true),
+            (
+                "git@github.com:[!a]cme/*",
+                "git@github.com:acme/x.git",
+                false,
+            ),
+            (
+                "https://github.com[:/]acme/*",
+                "https://github.com/acme/x.git",
+                false,
+            ),
+            ("*github.com[:/]acme/*", "git@github.com:acme/x.git", true),
+            (
+                "git@github.com:[[:alpha:]]cme/*",
+                "git@github.com:acme/x.git",
+                true,
+            ),
+            (
+                "git@github.com:[[:nope:]]cme/*",
+                "git@github.com:acme/x.git",
+                false,
+            ),
+        ];
+        for (pattern, url, expected) in cases {
+            assert_eq!(
+                matches_url(pattern, url),
+                expected,
+                "{} vs {}",
+                pattern,
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn wildmatch_escapes_and_double_star() {
+        assert!(wildmatch(br"a\*b", b"a*b"));
+        assert!(!wildmatch(br"a\*b", b"axb"));
+        assert!(wildmatch(b"a/**/b", b"a/b"));
+        assert!(wildmatch(b"a/**/b", b"a/x/y/b"));
+        assert!(!wildmatch(b"a/*/b", b"a/x/y/b"));
+        assert!(!wildmatch(b"a?b", b"a/b"));
+        assert!(!wildmatch(b"a**", b"a/b"));
+        assert!(!wildmatch(b"**b", b"a/b"));
+        assert!(wildmatch(b"**", b"a/b"));
+    }
+
+    #[test]
+    fn wildmatch_brackets() {
+        assert!(wildmatch(b"[a-c]x", b"bx"));
+        assert!(!wildmatch(b"[a-c]x", b"dx"));
+        assert!(wildmatch(b"[!a-c]x", b"dx"));
+        assert!(wildmatch(b"[]]", b"]"));
+        assert!(wildmatch(b"[a-]", b"-"));
+        assert!(wildmatch(b"[[:digit:]]x", b"7x"));
+        assert!(!wildmatch(b"[[:digit:]]x", b"ax"));
+        assert!(!wildmatch(b"[[:nope:]]", b"n"));
+        assert!(!wildmatch(b"a[/]b", b"a/b"));
+        assert!(!wildmatch(b"[ab", b"a"));
+        assert!(wildmatch(br"[\]]", b"]"));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::config::{self, Config, GitConfig};
+use crate::error::Result;
+use crate::git_config::{self, Scope};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+const ALLOWED_SIGNERS_KEY: &str = "gpg.ssh.allowedSignersFile";
+
+/// The allowed signers file lit generates, kept next to the config file.
+pub fn allowed_signers_path() -> PathBuf {
+    config::config_path().with_file_name("allowed_signers")
+}
+
+/// Whether `profile` signs with an SSH key.
+pub fn uses_ssh(profile: &GitConfig) -> bool {
+    profile.gpg_format.as_deref() == Some("ssh") && profile.signing_key.is_some()
+}
+
+/// Whether `line` looks like `<key type> <base64 key> [comment]`.
+fn is_public_key(line: &str) -> bool {
+    let mut fields = line.split_whitespace();
+    let key_type = fields.next().unwrap_or_default();
+    let known_type = ["ssh-", "ecdsa-", "sk-ssh-", "sk-ecdsa-"]
+        .iter()
+        .any(|prefix| key_type.starts_with(prefix));
+    let base64 = fields.next().is_some_and(|key| {
+        key.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"+/=".contains(&b))
+    });
+    known_type && base64
+}
+
+/// The public key line for an SSH signing key, which git accepts as a path
+/// to a public or private key file or inline as `key::<public key>`. For a
+/// private key the `.pub` file next to it is read.
+pub fn ssh_public_key(signing_key: &str) -> io::Result<String> {
+    let invalid = |what: String| io::Error::new(io::ErrorKind::InvalidData, what);
+    if let Some(key) = signing_key.strip_prefix("key::") {
+        let key = key.trim();
+        if !is_public_key(key) {
+            return Err(invalid(format!("{} is not an SSH public key", signing_key)));
+        }
+        return Ok(key.to_string());
+    }
+    let mut path = config::expand_tilde(signing_key);
+    let read = |path: &PathBuf| {
+        fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    };
+    let mut contents = read(&path)?;
+    if contents.contains("PRIVATE KEY-----") {
+        let mut public = path.into_os_string();
+        public.push(".pub");
+        path = PathBuf::from(public);
+        contents = read(&path)?;
+    }
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| invalid(format!("{}: no public key found", path.display())))?;
+    if !is_public_key(line) {
+        return Err(invalid(format!(
+            "{}: not an SSH public key",
+            path.display()
+        )));
+    }
+    Ok(line.to_string())
+}
+
+/// Rewrite the allowed signers file from every SSH-signing profile and point
+/// the global `gpg.ssh.allowedSignersFile` at it, unless it already names
+/// another file. Profiles whose key cannot be read are skipped with a
+/// warning.
+pub fn regenerate_allowed_signers(config: &Config) -> Result<()> {
+    let path = allowed_signers_path();
+    let mut names: Vec<&String> = config.profiles.keys().collect();
+    names.sort();
+
+    let mut lines = Vec::new();
+    for name in names {
+        let profile = &config.profiles[name];
+        if !uses_ssh(profile) {
+            continue;
+        }
+        match ssh_public_key(profile.signing_key.as_deref().unwrap_or_default()) {
+            Ok(key) => lines.push(format!("{} namespaces=\"git\" {}", profile.user_email, key)),
+            Err(e) => eprintln!(
+                "warning: profile '{}' left out of {}: {}",
+                name,
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    let path_str = path.display().to_string();
+    let current = git_config::get_all(&Scope::Global, ALLOWED_SIGNERS_KEY)?;
+    if lines.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        if current == [path_str.clone()] {
+            git_config::replace(&Scope::Global, ALLOWED_SIGNERS_KEY, &[])?;
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::File::create(&path)?;
+    writeln!(file, "# Generated by lit from its profiles. Do not edit.")?;
+    for line in &lines {
+        writeln!(file, "{}", line)?;
+    }
+    match current.last() {
+        None => git_config::replace(&Scope::Global, ALLOWED_SIGNERS_KEY, &[path_str])?,
+        Some(other) if *other != path_str => eprintln!(
+            "warning: {} is set to {}; add the entries from {} to it to verify profile signatures",
+            ALLOWED_SIGNERS_KEY, other, path_str
+        ),
+        Some(_) => {}
+    }
+    Ok(())
+}
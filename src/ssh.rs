@@ -0,0 +1,355 @@
+use crate::config::{self, Config, GitConfig};
+use crate::error::Result;
+use crate::managed::{self, SyncPlan};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Quote `s` for the POSIX shell git runs `core.sshCommand` through.
+pub fn shell_quote(s: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "/._-+:@=,".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// `core.sshCommand` that makes ssh authenticate with only `key`.
+pub fn ssh_command(key: &str) -> String {
+    let path = config::expand_tilde(key);
+    format!(
+        "ssh -i {} -o IdentitiesOnly=yes",
+        shell_quote(&path.display().to_string())
+    )
+}
+
+/// Split a command line into words the way `sh` would for plain quoting:
+/// single quotes, double quotes and backslash escapes.
+fn shell_words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// The identity file an ssh command line selects with `-i`, if any.
+pub fn identity_file(command: &str) -> Option<String> {
+    let mut words = shell_words(command).into_iter();
+    while let Some(word) = words.next() {
+        if word == "-i" {
+            return words.next();
+        }
+        if let Some(path) = word.strip_prefix("-i") {
+            return Some(path.to_string());
+        }
+    }
+    None
+}
+
+const BLOCK_BEGIN: &str =
+    "# BEGIN lit managed hosts (generated from lit profiles, edits will be lost)";
+const BLOCK_END: &str = "# END lit managed hosts";
+
+pub fn ssh_config_path() -> PathBuf {
+    config::expand_tilde("~/.ssh/config")
+}
+
+/// Quote a value for `ssh_config`, which accepts double-quoted arguments.
+fn ssh_config_quote(s: &str) -> String {
+    if s.contains(char::is_whitespace) {
+        format!("\"{}\"", s)
+    } else {
+        s.to_string()
+    }
+}
+
+/// Lines of the managed block for every profile declaring host aliases,
+/// markers included. Empty when no profile has any.
+pub fn render_host_block(config: &Config) -> Vec<String> {
+    let mut names: Vec<&String> = config.profiles.keys().collect();
+    names.sort();
+
+    let mut lines = Vec::new();
+    for name in names {
+        let profile = &config.profiles[name];
+        for host in &profile.ssh_hosts {
+            lines.push(format!("# profile: {}", name));
+            lines.push(format!("Host {}", host.alias));
+            lines.push(format!("    HostName {}", host.hostname));
+            if let Some(ref user) = host.user {
+                lines.push(format!("    User {}", user));
+            }
+            if let Some(ref key) = profile.ssh_key {
+                let path = config::expand_tilde(key).display().to_string();
+                lines.push(format!("    IdentityFile {}", ssh_config_quote(&path)));
+                lines.push(String::from("    IdentitiesOnly yes"));
+            }
+        }
+    }
+    if lines.is_empty() {
+        return lines;
+    }
+    lines.insert(0, String::from(BLOCK_BEGIN));
+    lines.push(String::from(BLOCK_END));
+    lines
+}
+
+/// Work out how `path` changes when its managed block is replaced with the
+/// one generated from `config`. A missing block is appended, since `Host`
+/// sections run to the next `Host`.
+pub fn plan_sync(config: &Config, path: &Path) -> Result<SyncPlan> {
+    managed::plan(path, BLOCK_BEGIN, BLOCK_END, render_host_block(config))
+}
+
+/// Create an ed25519 key pair for `profile` under `~/.ssh`, named after the
+/// profile and commented with `email`. ssh-keygen runs on the terminal so it
+/// can ask for a passphrase. Returns the key path as stored in profiles
+/// (`~/...`) and the public key.
+pub fn generate_key(profile: &str, email: &str) -> Result<(String, String)> {
+    let stored = format!("~/.ssh/id_ed25519_{}", managed::file_stem(profile));
+    let path = config::expand_tilde(&stored);
+    let mut public = path.clone().into_os_string();
+    public.push(".pub");
+    let public = PathBuf::from(public);
+    if path.exists() || public.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        )
+        .into());
+    }
+    if let Some(dir) = path.parent() {
+        if !dir.exists() {
+            fs::create_dir_all(dir)?;
+            managed::set_private(dir, 0o700)?;
+        }
+    }
+
+    let status = Command::new("ssh-keygen")
+        .args(["-t", "ed25519", "-C", email, "-f"])
+        .arg(&path)
+        .status()
+        .map_err(|e| io::Error::new(e.kind(), format!("could not run ssh-keygen: {}", e)))?;
+    if !status.success() {
+        return Err(io::Error::other(format!("ssh-keygen exited with {}", status)).into());
+    }
+    let key = fs::read_to_string(&public)?;
+    Ok((stored, key.trim().to_string()))
+}
+
+/// Run `ssh-add` against the agent behind `SSH_AUTH_SOCK` and return its
+/// stdout. Exit status 1 is accepted when `ok_if_one` is set.
+fn ssh_add(args: &[&str], key: Option<&str>, ok_if_one: bool) -> Result<String> {
+    if std::env::var_os("SSH_AUTH_SOCK").is_none() {
+        return Err(io::Error::other("SSH_AUTH_SOCK is not set; is ssh-agent running?").into());
+    }
+    let mut command = Command::new("ssh-add");
+    command.args(args);
+    if let Some(key) = key {
+        command.arg(config::expand_tilde(key));
+    }
+    let output = command
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("could not run ssh-add: {}", e)))?;
+    let accepted = output.status.success() || (ok_if_one && output.status.code() == Some(1));
+    if !accepted {
+        return Err(io::Error::other(format!(
+            "ssh-add exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Load the private key `key` into ssh-agent.
+pub fn agent_add(key: &str) -> Result<()> {
+    ssh_add(&[], Some(key), false).map(|_| ())
+}
+
+/// Remove `key` from ssh-agent if it is loaded. ssh-add finds the public
+/// half next to it.
+pub fn agent_remove(key: &str) -> Result<()> {
+    let mut public = config::expand_tilde(key).into_os_string();
+    public.push(".pub");
+    if let Ok(contents) = fs::read_to_string(&public) {
+        let blob = contents.split_whitespace().nth(1);
+        // `ssh-add -L` exits 1 when the agent holds no keys.
+        let loaded = ssh_add(&["-L"], None, true)?;
+        if !loaded
+            .lines()
+            .any(|line| line.split_whitespace().nth(1) == blob)
+        {
+            return Ok(());
+        }
+    }
+    ssh_add(&["-d"], Some(key), false).map(|_| ())
+}
+
+/// Point an SSH remote URL (`[user@]host:path` or `ssh://[user@]host/path`)
+/// at the profile's host alias for its host, so it uses the profile's key.
+/// Other URLs and hosts without an alias are returned unchanged.
+pub fn alias_url(profile: &GitConfig, url: &str) -> String {
+    let (prefix, rest) = match url.strip_prefix("ssh://") {
+        Some(rest) => ("ssh://", rest),
+        None if !url.contains("://") => ("", url),
+        None => return url.to_string(),
+    };
+    let end = if prefix.is_empty() {
+        match rest.find(':') {
+            Some(i) if !rest[..i].contains('/') => i,
+            _ => return url.to_string(),
+        }
+    } else {
+        rest.find(['/', ':']).unwrap_or(rest.len())
+    };
+    let (user, host) = match rest[..end].rsplit_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, &rest[..end]),
+    };
+    let Some(alias) = profile.ssh_hosts.iter().find(|h| h.hostname == host) else {
+        return url.to_string();
+    };
+    // The alias supplies its own user, if it has one.
+    let user = match (&alias.user, user) {
+        (Some(_), _) | (None, None) => String::new(),
+        (None, Some(user)) => format!("{}@", user),
+    };
+    format!("{}{}{}{}", prefix, user, alias.alias, &rest[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DefaultScope, SshHost};
+    use std::collections::HashMap;
+
+    fn config(alias: &str) -> Config {
+        let work = GitConfig {
+            ssh_key: Some(String::from("/keys/work")),
+            ssh_hosts: vec![SshHost {
+                alias: alias.to_string(),
+                hostname: String::from("github.com"),
+                user: Some(String::from("git")),
+            }],
+            ..Default::default()
+        };
+        Config {
+            version: 0,
+            profiles: HashMap::from([(String::from("work"), work)]),
+            current_profile: String::new(),
+            default_scope: DefaultScope::default(),
+            rules: Vec::new(),
+        }
+    }
+
+    fn scratch(name: &str, contents: Option<&str>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lit-ssh-{}-{}", std::process::id(), name));
+        match contents {
+            Some(contents) => fs::write(&path, contents).unwrap(),
+            None => {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        path
+    }
+
+    fn sync(config: &Config, path: &Path) -> SyncPlan {
+        let plan = plan_sync(config, path).unwrap();
+        managed::write(&plan).unwrap();
+        plan
+    }
+
+    #[test]
+    fn the_host_block_is_appended_after_the_users_hosts() {
+        let path = scratch("append", Some("Host *\n    ServerAliveInterval 60\n"));
+        sync(&config("github.com-work"), &path);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!(
+                "Host *\n    ServerAliveInterval 60\n\n{}\n# profile: work\nHost github.com-work\n    \
+                 HostName github.com\n    User git\n    IdentityFile /keys/work\n    \
+                 IdentitiesOnly yes\n{}\n",
+                BLOCK_BEGIN, BLOCK_END
+            )
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_host_block_is_replaced_in_place_and_reruns_change_nothing() {
+        let path = scratch("replace", None);
+        sync(&config("old-alias"), &path);
+        let block = fs::read_to_string(&path).unwrap();
+        let head = "Include ~/.ssh/extra\r\n\n";
+        let tail = "\nHost example\n  User me";
+        fs::write(&path, format!("{}{}{}", head, block, tail)).unwrap();
+
+        let plan = sync(&config("new-alias"), &path);
+        assert!(plan.removed.contains(&String::from("Host old-alias")));
+        let replaced = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            replaced,
+            format!(
+                "{}{}{}",
+                head,
+                block.replace("old-alias", "new-alias"),
+                tail
+            )
+        );
+
+        assert!(sync(&config("new-alias"), &path).is_noop());
+        assert_eq!(fs::read_to_string(&path).unwrap(), replaced);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn an_unterminated_host_block_is_an_error() {
+        let contents = format!(
+            "{}\nHost old\n# a user's own Host below\nHost mine\n",
+            BLOCK_BEGIN
+        );
+        let path = scratch("unterminated", Some(&contents));
+        assert!(plan_sync(&config("github.com-work"), &path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,5 +1,8 @@
-use crate::config::load_config;
+use crate::config::{load_config, Config};
+use crate::error::Result;
+use crate::git_config::Scope;
 use crate::input::{handle_input, InputMode};
+use crate::profile;
 use crossterm::{
     cursor,
     event::{self, DisableMouseCapture, EnableMouseCapture, KeyCode},
@@ -15,7 +18,7 @@ use tui::{
     Terminal,
 };
 
-pub fn run_tui() -> io::Result<()> {
+pub fn run_tui() -> Result<()> {
     let mut stdout = io::stdout();
     execute!(
         stdout,
@@ -42,7 +45,7 @@ pub fn run_tui() -> io::Result<()> {
     result
 }
 
-fn run_app<B: tui::backend::Backend>(terminal: &mut Terminal<B>) -> io::Result<()> {
+fn run_app<B: tui::backend::Backend>(terminal: &mut Terminal<B>) -> Result<()> {
     let mut state = ListState::default();
     let mut delete_state = ListState::default();
     let mut switch_state = ListState::default();
@@ -66,18 +69,35 @@ fn run_app<B: tui::backend::Backend>(terminal: &mut Terminal<B>) -> io::Result<(
     let mut selected_profile_to_update: Option<String> = None; // New selected profile to update
     let mut delete_options: Vec<String> = Vec::new();
     let mut switch_options: Vec<String> = Vec::new();
+    let mut switch_scope: Option<Scope> = None;
+    let mut scope_file = String::new();
+    let mut editing_profile: Option<String> = None;
+    let mut extra_input = String::new();
+    let mut pending_keygen: Option<String> = None;
     let mut update_options: Vec<String> = Vec::new(); // New options for updating
-    let mut config = load_config();
+    let mut config = load_config()?;
+    let mut error_message: Option<String> = None;
 
     loop {
         terminal.draw(|f| {
             let size = f.size();
+            let mut constraints = vec![Constraint::Percentage(20), Constraint::Min(0)];
+            if error_message.is_some() {
+                constraints.push(Constraint::Length(3));
+            }
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .margin(2)
-                .constraints([Constraint::Percentage(20), Constraint::Percentage(80)].as_ref())
+                .constraints(constraints)
                 .split(size);
 
+            if let Some(ref message) = error_message {
+                let paragraph = Paragraph::new(message.as_str())
+                    .style(tui::style::Style::default().fg(tui::style::Color::Red))
+                    .block(Block::default().title("Error").borders(Borders::ALL));
+                f.render_widget(paragraph, chunks[2]);
+            }
+
             match input_mode {
                 InputMode::InputProfileName => {
                     let paragraph = Paragraph::new(format!("Profile Name: {}", profile_name))
@@ -114,6 +134,41 @@ fn run_app<B: tui::backend::Backend>(terminal: &mut Terminal<B>) -> io::Result<(
                     );
                     f.render_widget(paragraph, chunks[1]);
                 }
+                InputMode::ConfirmGenerateSshKey => {
+                    let paragraph = Paragraph::new(
+                        "Generate an ed25519 SSH key for this profile with ssh-keygen? (y/n)",
+                    )
+                    .block(Block::default().title("Generate SSH Key").borders(Borders::ALL));
+                    f.render_widget(paragraph, chunks[1]);
+                }
+                InputMode::EditExtraConfig => {
+                    let entries: Vec<ListItem> = editing_profile
+                        .as_ref()
+                        .and_then(|name| config.profiles.get(name))
+                        .map(|profile| {
+                            profile
+                                .extra_config
+                                .iter()
+                                .flat_map(|(key, values)| {
+                                    values.iter().map(move |v| ListItem::new(format!("{} = {}", key, v)))
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    let list = List::new(entries).block(
+                        Block::default()
+                            .title("Extra Git Config")
+                            .borders(Borders::ALL),
+                    );
+                    f.render_widget(list, chunks[0]);
+
+                    let paragraph = Paragraph::new(format!(
+                        "> {}\n\n'key=value' to set, 'key+=value' to add a value, '!key' to remove, 'Enter' on an empty line to finish.",
+                        extra_input
+                    ))
+                    .block(Block::default().title("Edit Extra Git Config").borders(Borders::ALL));
+                    f.render_widget(paragraph, chunks[1]);
+                }
                 InputMode::ListingProfiles => {
                     let profile_items: Vec<ListItem> = config
                         .profiles
@@ -154,17 +209,30 @@ fn run_app<B: tui::backend::Backend>(terminal: &mut Terminal<B>) -> io::Result<(
                         .map(|o| ListItem::new(o.to_string()))
                         .collect();
                     let list = List::new(items)
-                        .block(Block::default().borders(Borders::ALL).title("Select Profile to Switch"))
+                        .block(Block::default().borders(Borders::ALL).title(format!(
+                            "Select Profile to Switch (scope: {})",
+                            switch_scope
+                                .as_ref()
+                                .map_or_else(|| String::from("default"), Scope::to_string)
+                        )))
                         .highlight_style(
                             tui::style::Style::default().bg(tui::style::Color::Yellow),
                         );
 
                     f.render_stateful_widget(list, chunks[0], &mut switch_state);
 
-                    let paragraph = Paragraph::new("Use arrow keys or 'j', 'k' to navigate, 'Enter' to select, 'b' to go back to main menu.")
+                    let paragraph = Paragraph::new("Use arrow keys or 'j', 'k' to navigate, 'Tab' to change scope, 'f' to write to a file, 'Enter' to select, 'b' to go back to main menu.")
                         .block(Block::default().title("Instructions").borders(Borders::ALL));
                     f.render_widget(paragraph, chunks[1]);
                 }
+                InputMode::SwitchProfileFile => {
+                    let paragraph = Paragraph::new(format!("Config File: {}", scope_file)).block(
+                        Block::default()
+                            .title("Enter Git Config File Path")
+                            .borders(Borders::ALL),
+                    );
+                    f.render_widget(paragraph, chunks[1]);
+                }
                 InputMode::UpdateProfile => {
                     update_options = config.profiles.keys().cloned().collect();
                     let items: Vec<ListItem> = update_options
@@ -227,7 +295,8 @@ fn run_app<B: tui::backend::Backend>(terminal: &mut Terminal<B>) -> io::Result<(
                 }
 
                 let options_slice: Vec<&str> = options.iter().map(AsRef::as_ref).collect();
-                let _ = handle_input(
+                error_message = None;
+                if let Err(e) = handle_input(
                     key,
                     &mut input_mode,
                     &mut profile_name,
@@ -241,13 +310,53 @@ fn run_app<B: tui::backend::Backend>(terminal: &mut Terminal<B>) -> io::Result<(
                     &mut selected_profile_to_switch,
                     &mut switch_state,
                     &switch_options,
+                    &mut switch_scope,
+                    &mut scope_file,
+                    &mut editing_profile,
+                    &mut extra_input,
+                    &mut pending_keygen,
                     &mut selected_profile_to_update,
                     &mut update_state, // New update state
                     &update_options,
                     &mut config,
-                );
+                ) {
+                    error_message = Some(e.to_string());
+                }
+                if let Some(name) = pending_keygen.take() {
+                    if let Err(e) = generate_key_outside_tui(terminal, &mut config, &name) {
+                        error_message = Some(e.to_string());
+                    }
+                }
             }
         }
     }
     Ok(())
 }
+
+/// Leave the alternate screen so ssh-keygen can prompt for a passphrase on
+/// the real terminal, then show the public key before returning to the TUI.
+fn generate_key_outside_tui<B: tui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    config: &mut Config,
+    name: &str,
+) -> Result<()> {
+    terminal::disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+
+    let result = profile::generate_ssh_key(config, name);
+    match result {
+        Ok(ref public_key) => println!(
+            "\nAdd this public key to your hosting service:\n{}\n",
+            public_key
+        ),
+        Err(ref e) => println!("\nError: {}\n", e),
+    }
+    println!("Press Enter to return to lit.");
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    terminal::enable_raw_mode()?;
+    terminal.clear()?;
+    result.map(|_| ())
+}
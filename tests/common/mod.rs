@@ -0,0 +1,117 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A throwaway home directory with its own lit config and global git config,
+/// removed on drop.
+pub struct Sandbox {
+    pub home: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new() -> Sandbox {
+        let n = NEXT.fetch_add(1, Ordering::SeqCst);
+        let home = std::env::temp_dir().join(format!("lit-test-{}-{}", std::process::id(), n));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(&home).unwrap();
+        Sandbox {
+            home: home.canonicalize().unwrap(),
+        }
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.home.join(".config").join("lit").join("config.json")
+    }
+
+    fn env(&self, command: &mut Command) {
+        command
+            .env("HOME", &self.home)
+            .env("XDG_CONFIG_HOME", self.home.join(".config"))
+            .env("LIT_CONFIG", self.config_path())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env_remove("GIT_CONFIG_GLOBAL")
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE")
+            .env_remove("SSH_AUTH_SOCK");
+    }
+
+    /// Run lit in `dir` and return its output.
+    pub fn lit_in(&self, dir: &Path, args: &[&str]) -> Output {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_lit"));
+        self.env(&mut command);
+        command.current_dir(dir).args(args).output().unwrap()
+    }
+
+    /// Run lit in `dir` with `input` on its stdin.
+    pub fn lit_with_input(&self, dir: &Path, args: &[&str], input: &str) -> Output {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_lit"));
+        self.env(&mut command);
+        let mut child = command
+            .current_dir(dir)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Run lit in `dir` with its stdout closed before it writes, like a
+    /// pipe into `head` that has read enough.
+    pub fn lit_closed_stdout(&self, dir: &Path, args: &[&str]) -> Output {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_lit"));
+        self.env(&mut command);
+        let mut child = command
+            .current_dir(dir)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        drop(child.stdout.take());
+        child.wait_with_output().unwrap()
+    }
+
+    /// Run lit in the home directory and fail the test if it fails.
+    pub fn lit(&self, args: &[&str]) -> String {
+        let output = self.lit_in(&self.home, args);
+        assert!(
+            output.status.success(),
+            "lit {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    /// Run git in `dir` and return its trimmed stdout, whatever the status.
+    pub fn git_in(&self, dir: &Path, args: &[&str]) -> String {
+        let mut command = Command::new("git");
+        self.env(&mut command);
+        let output = command.current_dir(dir).args(args).output().unwrap();
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    pub fn git(&self, args: &[&str]) -> String {
+        self.git_in(&self.home, args)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.home);
+    }
+}
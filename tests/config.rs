@@ -0,0 +1,92 @@
+mod common;
+
+use common::Sandbox;
+use std::fs;
+use std::path::PathBuf;
+
+fn backup(sandbox: &Sandbox, n: usize) -> PathBuf {
+    let mut name = sandbox.config_path().into_os_string();
+    name.push(format!(".bak.{}", n));
+    PathBuf::from(name)
+}
+
+#[test]
+fn every_write_rotates_the_backups() {
+    let sandbox = Sandbox::new();
+    for n in 0..7 {
+        sandbox.lit(&["add-profile", &format!("p{}", n), "P", "p@x.org"]);
+    }
+    // Seven writes: the first created the file, five backups are kept.
+    for n in 1..=5 {
+        let contents = fs::read_to_string(backup(&sandbox, n)).unwrap();
+        // Backup n predates the last n writes, so p0..p(6-n) exist.
+        assert!(
+            contents.contains(&format!("\"p{}\"", 6 - n)),
+            "backup {}",
+            n
+        );
+        assert!(
+            !contents.contains(&format!("\"p{}\"", 7 - n)),
+            "backup {}",
+            n
+        );
+    }
+    assert!(!backup(&sandbox, 6).exists());
+}
+
+#[test]
+fn restore_brings_back_a_backup_and_backs_up_the_current_file() {
+    let sandbox = Sandbox::new();
+    sandbox.lit(&["add-profile", "work", "W", "w@x.org"]);
+    let before = fs::read_to_string(sandbox.config_path()).unwrap();
+    sandbox.lit(&["delete-profile", "work"]);
+    let deleted = fs::read_to_string(sandbox.config_path()).unwrap();
+
+    sandbox.lit(&["config", "restore", "1"]);
+    assert_eq!(fs::read_to_string(sandbox.config_path()).unwrap(), before);
+    assert_eq!(fs::read_to_string(backup(&sandbox, 1)).unwrap(), deleted);
+    assert!(sandbox.lit(&["list-profile"]).contains("work"));
+}
+
+#[test]
+fn restore_refuses_an_invalid_backup() {
+    let sandbox = Sandbox::new();
+    sandbox.lit(&["add-profile", "work", "W", "w@x.org"]);
+    sandbox.lit(&["add-profile", "home", "H", "h@x.org"]);
+    let current = fs::read_to_string(sandbox.config_path()).unwrap();
+    fs::write(backup(&sandbox, 1), "{ not json").unwrap();
+
+    let output = sandbox.lit_in(&sandbox.home, &["config", "restore", "1"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(fs::read_to_string(sandbox.config_path()).unwrap(), current);
+}
+
+#[test]
+fn a_v0_config_is_migrated_once_with_a_backup() {
+    let sandbox = Sandbox::new();
+    let v0 = r#"{"profiles": {"work": {"user_name": "W", "user_email": "w@x.org"}}}"#;
+    fs::create_dir_all(sandbox.config_path().parent().unwrap()).unwrap();
+    fs::write(sandbox.config_path(), v0).unwrap();
+
+    assert!(sandbox.lit(&["list-profile"]).contains("work"));
+    let migrated: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(sandbox.config_path()).unwrap()).unwrap();
+    assert_eq!(migrated["version"], 1);
+    assert_eq!(migrated["current_profile"], "");
+    assert_eq!(fs::read_to_string(backup(&sandbox, 1)).unwrap(), v0);
+
+    // Loading the migrated file again neither rewrites it nor rotates backups.
+    sandbox.lit(&["list-profile"]);
+    assert!(!backup(&sandbox, 2).exists());
+}
+
+#[test]
+fn a_config_from_a_newer_lit_is_refused() {
+    let sandbox = Sandbox::new();
+    fs::create_dir_all(sandbox.config_path().parent().unwrap()).unwrap();
+    fs::write(sandbox.config_path(), r#"{"version": 99, "profiles": {}}"#).unwrap();
+
+    let output = sandbox.lit_in(&sandbox.home, &["list-profile"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("newer"));
+}
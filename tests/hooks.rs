@@ -0,0 +1,68 @@
+mod common;
+
+use common::Sandbox;
+use std::fs;
+
+#[test]
+fn a_local_install_goes_to_the_common_hooks_dir() {
+    let sandbox = Sandbox::new();
+    let repo = sandbox.home.join("repo");
+    let tree = sandbox.home.join("tree");
+    sandbox.git(&["init", repo.to_str().unwrap()]);
+    sandbox.git_in(
+        &repo,
+        &[
+            "-c",
+            "user.name=U",
+            "-c",
+            "user.email=u@x.org",
+            "commit",
+            "--allow-empty",
+            "-m",
+            "first",
+        ],
+    );
+    sandbox.git_in(&repo, &["worktree", "add", tree.to_str().unwrap()]);
+
+    let output = sandbox.lit_in(&tree, &["hooks", "install"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(repo.join(".git/hooks/pre-commit").is_file());
+}
+
+#[test]
+fn a_local_install_never_touches_the_global_hooks() {
+    let sandbox = Sandbox::new();
+    let repo = sandbox.home.join("repo");
+    sandbox.git(&["init", repo.to_str().unwrap()]);
+    sandbox.lit(&["hooks", "install", "--global"]);
+    let global = sandbox.home.join(".config/lit/hooks");
+    let pre_commit = fs::read_to_string(global.join("pre-commit")).unwrap();
+    let pre_push = fs::read_to_string(global.join("pre-push")).unwrap();
+
+    // The global pre-commit hook already checks; a local one would too.
+    let output = sandbox.lit_in(&repo, &["hooks", "install"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(!repo.join(".git/hooks/pre-commit").exists());
+
+    // The global pre-push hook only forwards to the repository's own.
+    let output = sandbox.lit_in(&repo, &["hooks", "install", "--pre-push"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(repo.join(".git/hooks/pre-push").is_file());
+
+    assert_eq!(
+        fs::read_to_string(global.join("pre-commit")).unwrap(),
+        pre_commit
+    );
+    assert_eq!(
+        fs::read_to_string(global.join("pre-push")).unwrap(),
+        pre_push
+    );
+}
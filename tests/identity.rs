@@ -0,0 +1,72 @@
+mod common;
+
+use common::Sandbox;
+use std::fs;
+
+#[test]
+fn an_email_pin_is_checked_without_a_profile() {
+    let sandbox = Sandbox::new();
+    sandbox.lit(&["add-profile", "work", "W", "w@x.org"]);
+    let repo = sandbox.home.join("repo");
+    sandbox.git(&["init", repo.to_str().unwrap()]);
+    fs::write(repo.join(".lit-profile"), "ci@corp.example\n").unwrap();
+
+    sandbox.git_in(&repo, &["config", "user.email", "ci@corp.example"]);
+    let output = sandbox.lit_in(&repo, &["check-identity"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    sandbox.git_in(&repo, &["config", "user.email", "w@x.org"]);
+    let output = sandbox.lit_in(&repo, &["check-identity"]);
+    assert_eq!(output.status.code(), Some(9));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("pins ci@corp.example"), "{}", stderr);
+}
+
+#[test]
+fn an_email_pin_is_checked_on_push_without_a_profile() {
+    let sandbox = Sandbox::new();
+    sandbox.lit(&["add-profile", "work", "W", "w@x.org"]);
+    let repo = sandbox.home.join("repo");
+    sandbox.git(&["init", repo.to_str().unwrap()]);
+    fs::write(repo.join(".lit-profile"), "ci@corp.example\n").unwrap();
+    let commit = |email: &str| {
+        sandbox.git_in(
+            &repo,
+            &[
+                "-c",
+                "user.name=CI",
+                "-c",
+                &format!("user.email={}", email),
+                "commit",
+                "--allow-empty",
+                "-m",
+                email,
+            ],
+        );
+        sandbox.git_in(&repo, &["rev-parse", "HEAD"])
+    };
+    let push = |sha: &str| {
+        let input = format!(
+            "refs/heads/main {} refs/heads/main {}\n",
+            sha,
+            "0".repeat(40)
+        );
+        sandbox.lit_with_input(&repo, &["check-push", "origin", "git@x.org:ci.git"], &input)
+    };
+
+    let output = push(&commit("ci@corp.example"));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = push(&commit("w@x.org"));
+    assert_eq!(output.status.code(), Some(9));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not by <ci@corp.example>"), "{}", stderr);
+}
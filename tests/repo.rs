@@ -0,0 +1,24 @@
+mod common;
+
+use common::Sandbox;
+
+#[test]
+fn init_writes_the_profile_through_a_separate_git_dir() {
+    let sandbox = Sandbox::new();
+    sandbox.lit(&["add-profile", "work", "W", "w@x.org"]);
+    let dir = sandbox.home.join("repo");
+    let git_dir = sandbox.home.join("repo.git");
+    sandbox.git(&[
+        "init",
+        "--separate-git-dir",
+        git_dir.to_str().unwrap(),
+        dir.to_str().unwrap(),
+    ]);
+
+    sandbox.lit(&["init", "-p", "work", dir.to_str().unwrap()]);
+    assert_eq!(
+        sandbox.git_in(&dir, &["config", "--local", "user.email"]),
+        "w@x.org"
+    );
+    assert!(dir.join(".git").is_file());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::Sandbox;
+use std::fs;
+
+#[test]
+fn a_global_switch_keeps_the_rule_includes_last() {
+    let sandbox = Sandbox::new();
+    let work = sandbox.home.join("work");
+    let repo = work.join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    sandbox.lit(&[
+        "add-profile",
+        "work",
+        "W",
+        "w@x.org",
+        "--directory",
+        work.to_str().unwrap(),
+    ]);
+    sandbox.lit(&["add-profile", "home", "H", "h@x.org"]);
+    sandbox.lit(&["rules", "apply"]);
+    sandbox.lit(&["switch-profile", "home"]);
+    sandbox.git_in(&repo, &["init"]);
+
+    assert_eq!(sandbox.git_in(&repo, &["config", "user.email"]), "w@x.org");
+    assert_eq!(sandbox.git(&["config", "user.email"]), "h@x.org");
+}
+
+#[test]
+fn listings_into_a_closed_pipe_exit_quietly() {
+    let sandbox = Sandbox::new();
+    let repo = sandbox.home.join("repo");
+    sandbox.git(&["init", repo.to_str().unwrap()]);
+    sandbox.lit(&["add-profile", "work", "W", "w@x.org"]);
+
+    for args in [
+        &["rules", "test"][..],
+        &["rules", "list"],
+        &["list-profile"],
+    ] {
+        let output = sandbox.lit_closed_stdout(&repo, args);
+        assert!(
+            output.status.success(),
+            "lit {:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(output.stderr.is_empty(), "lit {:?}", args);
+    }
+}